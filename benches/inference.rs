@@ -0,0 +1,46 @@
+//! Generations and game steps per second with per-game and batched inference
+//! The runs evolve different populations, game steps per second is the steadier figure
+//! Run with `cargo bench`
+
+use std::time::Instant;
+
+use snake::stream::Stream;
+
+const NUM_GENS: usize = 20;
+
+fn run(name: &str, is_batched: bool) {
+    let mut stream: Stream = Stream::new();
+    let mut num_steps = 0;
+    let start = Instant::now();
+
+    for _ in 0..NUM_GENS {
+        loop {
+            if is_batched {
+                stream.update_batched();
+            } else {
+                stream.update_sequential();
+            }
+
+            let alive = stream.counts().alive;
+            if alive == 0 {
+                break;
+            }
+            num_steps += alive;
+        }
+        stream.update_stagnation();
+        stream.reset();
+    }
+
+    let secs = start.elapsed().as_secs_f32();
+    println!(
+        "{:<10} {:>8.2} gens/sec {:>12.0} game steps/sec",
+        name,
+        NUM_GENS as f32 / secs,
+        num_steps as f32 / secs
+    );
+}
+
+fn main() {
+    run("sequential", false);
+    run("batched", true);
+}
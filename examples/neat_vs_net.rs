@@ -0,0 +1,68 @@
+//! Headless comparison of the fixed topology `Net` against the evolved topology NEAT `Genome`
+//! Both run the same number of generations with the settings from `src/configs.rs`
+//! Run with `cargo run --release --example neat_vs_net`
+
+use std::io;
+use std::time::Instant;
+
+use snake::brain::Brain;
+use snake::neat::Genome;
+use snake::nn::Net;
+use snake::pop::Population;
+
+const NUM_GENS: usize = 50;
+
+struct RunSummary {
+    name: &'static str,
+    max_score: usize,
+    best_avg_score: f32,
+    secs: f32,
+}
+
+fn run<B: Brain>(name: &'static str) -> io::Result<RunSummary> {
+    let mut pop = Population::<B>::new()?;
+    let mut max_score = 0;
+    let start = Instant::now();
+
+    for gen in 0..NUM_GENS {
+        while pop.update().alive > 0 {}
+        pop.validate_generation();
+
+        let summary = pop.get_gen_summary();
+        max_score = max_score.max(summary.max_score);
+        println!(
+            "{:<6} gen: {:>3}, score: {:>3}, best avg: {:>6.2}",
+            name, gen, summary.max_score, summary.best_avg_score
+        );
+        pop.reset();
+    }
+
+    Ok(RunSummary {
+        name,
+        max_score,
+        best_avg_score: pop
+            .hall_of_fame()
+            .best()
+            .map(|e| e.eval.mean)
+            .unwrap_or(0.0),
+        secs: start.elapsed().as_secs_f32(),
+    })
+}
+
+fn main() -> io::Result<()> {
+    let results = [run::<Net>("net")?, run::<Genome>("neat")?];
+
+    println!();
+    println!(
+        "{:<6} {:>10} {:>10} {:>10}",
+        "brain", "max score", "best avg", "secs"
+    );
+    for r in results.iter() {
+        println!(
+            "{:<6} {:>10} {:>10.2} {:>10.1}",
+            r.name, r.max_score, r.best_avg_score, r.secs
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Arena
+//! Several snakes with their own brains on one board, sharing the food
+//! Snakes move at the same time and die on each other's bodies, they are ranked by length then survival
+
+use crate::brain::Brain;
+use crate::game::{Game, GameConfig};
+use crate::level;
+use crate::*;
+
+/// One snake per brain on the same board, seeded like `Game::with_seed`
+/// Food eaten by the k-th snake is placed from `seed + k + 1`, apart from the board itself
+/// Snakes spawn as a single cell spread along the middle row, the start config only sets the direction
+/// Obstacles and food are placed around every spawn
+pub fn new_arena<B: Brain>(config: &GameConfig, brains: &[B], seed: u64) -> Vec<Game<B>> {
+    let spawns: Vec<Point> = (0..brains.len())
+        .map(|idx| spawn_point(config, idx, brains.len()))
+        .collect();
+
+    let mut snakes = Vec::with_capacity(brains.len());
+    for (idx, (brain, &spawn)) in brains.iter().zip(spawns.iter()).enumerate() {
+        // Same board for everyone, then every snake places the food it eats from its own seed
+        let mut snake = Game::with_reserved_cells(config, seed, &spawns);
+        snake.reseed(seed.wrapping_add(idx as u64 + 1));
+        snake.brain = brain.clone();
+        snake.respawn(spawn);
+        snakes.push(snake);
+    }
+
+    sync(&mut snakes, 0);
+    snakes
+}
+
+/// Moves every alive snake at the same time, then updates the bonus food once
+/// Every snake picks its direction and moves against the other snakes as they were
+/// at the start of the step, heads that end up on the same cell crash into each other
+pub fn update<B: Brain>(snakes: &mut [Game<B>]) {
+    let alive: Vec<usize> = (0..snakes.len())
+        .filter(|&idx| !snakes[idx].is_complete)
+        .collect();
+    let start_cells: Vec<Vec<Point>> = alive
+        .iter()
+        .map(|&idx| opponent_cells(snakes, idx))
+        .collect();
+
+    let mut dirs = Vec::with_capacity(alive.len());
+    for (&idx, cells) in alive.iter().zip(start_cells.iter()) {
+        snakes[idx].set_opponents(cells.clone());
+        dirs.push(snakes[idx].next_dir());
+    }
+    for (&idx, &dir) in alive.iter().zip(dirs.iter()) {
+        snakes[idx].step(dir);
+        sync(snakes, idx);
+    }
+
+    let heads: Vec<Point> = alive.iter().map(|&idx| snakes[idx].head).collect();
+    for (pos, &idx) in alive.iter().enumerate() {
+        let other_heads = heads
+            .iter()
+            .enumerate()
+            .filter(|&(other, _)| other != pos)
+            .map(|(_, &head)| head)
+            .collect();
+        snakes[idx].set_opponents(other_heads);
+        snakes[idx].handle_opponent_collision();
+    }
+
+    if let Some(idx) = snakes.iter().position(|s| !s.is_complete) {
+        let opponents = opponent_cells(snakes, idx);
+        snakes[idx].set_opponents(opponents);
+        snakes[idx].update_bonus_food();
+        sync(snakes, idx);
+    }
+}
+
+pub fn is_complete<B: Brain>(snakes: &[Game<B>]) -> bool {
+    snakes.iter().all(|s| s.is_complete)
+}
+
+/// Updates the arena until every snake is done
+pub fn play<B: Brain>(snakes: &mut [Game<B>]) {
+    while !is_complete(snakes) {
+        update(snakes);
+    }
+}
+
+/// Indices of the snakes, best first
+/// Longest snake first, the one that survived longest breaks ties
+pub fn ranking<B: Brain>(snakes: &[Game<B>]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..snakes.len()).collect();
+    order.sort_by_key(|&idx| std::cmp::Reverse((snakes[idx].score(), snakes[idx].num_steps())));
+    order
+}
+
+/// Every snake sees the food as left by the snake at `source`
+fn sync<B: Brain>(snakes: &mut [Game<B>], source: usize) {
+    let food = snakes[source].food.clone();
+    for snake in snakes.iter_mut() {
+        snake.food.clone_from(&food);
+    }
+}
+
+/// Cells of the alive snakes other than `idx`, dead snakes leave the board
+fn opponent_cells<B: Brain>(snakes: &[Game<B>], idx: usize) -> Vec<Point> {
+    snakes
+        .iter()
+        .enumerate()
+        .filter(|(other, s)| *other != idx && !s.is_complete)
+        .flat_map(|(_, s)| s.body.iter().copied())
+        .collect()
+}
+
+/// Evenly spaced along the middle row, moved up or down when it's a wall of the level
+fn spawn_point(config: &GameConfig, idx: usize, num_snakes: usize) -> Point {
+    let (w, h) = (config.grid_w, config.grid_h);
+    let is_wall = |pt: Point| {
+        let is_border = pt.x <= 0 || pt.y <= 0 || pt.x >= w || pt.y >= h;
+        is_border
+            || config
+                .level
+                .as_ref()
+                .is_some_and(|level| level.cells()[level::cell_index(level.grid_w, pt)])
+    };
+
+    let x = w * (idx as i32 + 1) / (num_snakes as i32 + 1);
+    let x = x.clamp(1, w - 1);
+    for offset in 0..h {
+        for y in [h / 2 + offset, h / 2 - offset] {
+            let pt = Point::new(x, y);
+            if !is_wall(pt) {
+                return pt;
+            }
+        }
+    }
+
+    Point::new(w / 2, h / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::food::{Food, FoodKind};
+    use crate::game::TerminationReason;
+    use crate::level::Obstacles;
+    use crate::nn::Net;
+
+    #[test]
+    fn spawn_areas_are_clear_of_obstacles_and_food() {
+        let config = GameConfig {
+            obstacles: Obstacles::Scattered(0.5),
+            ..GameConfig::DEFAULT
+        };
+        let brains: Vec<Net> = (0..4).map(|_| Net::random(&config)).collect();
+        for seed in 0..50 {
+            let snakes = new_arena(&config, &brains, seed);
+            assert!(snakes
+                .iter()
+                .all(|s| s.state().obstacles == snakes[0].state().obstacles));
+            for snake in snakes.iter() {
+                // Room to move around every spawn
+                for dy in -2..=2 {
+                    for dx in -2..=2 {
+                        let pt = Point::new(snake.head.x + dx, snake.head.y + dy);
+                        assert!(!snake.is_obstacle(pt));
+                    }
+                }
+                for other in snakes.iter() {
+                    assert!(!other.is_obstacle(snake.head));
+                    assert!(other.food.iter().all(|f| f.pos != snake.head));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snakes_place_food_from_their_own_seed() {
+        let config = GameConfig::DEFAULT;
+        let brains: Vec<Net> = (0..2).map(|_| Net::random(&config)).collect();
+        for seed in 0..10 {
+            // Every snake eats the food under its head and places the next one
+            let placed: Vec<Point> = new_arena(&config, &brains, seed)
+                .into_iter()
+                .map(|mut snake| {
+                    snake.food = vec![Food::new(snake.head, FoodKind::Normal)];
+                    snake.step(snake.dir);
+                    snake.food[0].pos
+                })
+                .collect();
+            assert_ne!(placed[0], placed[1]);
+        }
+    }
+
+    /// Net that always picks the output at `dir_idx`, see `Game::output_to_dir`
+    fn fixed_dir_net(config: &GameConfig, dir_idx: usize) -> Net {
+        let shape = Net::random(config).shape();
+        let mut params = vec![0.0; Net::with_shape(&shape).num_params()];
+        let num_hidden_params = shape[1] * (shape[0] + 1);
+        params[num_hidden_params + dir_idx * (shape[1] + 1)] = 10.0;
+        Net::from_params(&shape, &params).unwrap()
+    }
+
+    #[test]
+    fn head_on_collisions_end_both_snakes() {
+        let config = GameConfig {
+            obstacles: Obstacles::None,
+            ..GameConfig::DEFAULT
+        };
+        // Left is output 0 and right output 1
+        for (first, second) in [(1, 0), (0, 1)] {
+            let brains = [
+                fixed_dir_net(&config, first),
+                fixed_dir_net(&config, second),
+            ];
+            let mut snakes = new_arena(&config, &brains, 0);
+            let (left, right) = if first == 1 { (0, 1) } else { (1, 0) };
+            snakes[left].respawn(Point::new(5, 5));
+            snakes[left].dir = FourDirs::Right;
+            snakes[right].respawn(Point::new(7, 5));
+            snakes[right].dir = FourDirs::Left;
+            for snake in snakes.iter_mut() {
+                snake.food = vec![Food::new(Point::new(1, 1), FoodKind::Normal)];
+            }
+
+            update(&mut snakes);
+            for snake in snakes.iter() {
+                assert_eq!(snake.head, Point::new(6, 5));
+                assert_eq!(snake.termination(), Some(TerminationReason::Body));
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Tournament
+//! Ranks saved brains against each other, eg. the hall of fame of several runs
+//! cargo run --release --bin tournament -- <brain files or dirs> [--arena] [--games N] [--csv PATH]
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use snake::brain::Brain;
+use snake::game::GameConfig;
+use snake::hall_of_fame;
+use snake::tournament::{self, TournamentMode};
+use snake::*;
+
+const USAGE: &str = "Usage: tournament <brain files or dirs> [--arena] [--games N] [--csv PATH]";
+
+fn main() {
+    let mut paths = Vec::new();
+    let mut mode = TournamentMode::Solo;
+    let mut num_games = TOURNAMENT_NUM_GAMES;
+    let mut csv_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--arena" => mode = TournamentMode::Arena,
+            "--games" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => num_games = n,
+                None => exit_with(USAGE),
+            },
+            "--csv" => match args.next() {
+                Some(path) => csv_path = Some(path),
+                None => exit_with(USAGE),
+            },
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        exit_with(USAGE);
+    }
+
+    let mut config = GameConfig::default();
+    if let Err(e) = config.load_level() {
+        exit_with(&format!("Failed to load the level: {}", e));
+    }
+    let mut brains = Vec::new();
+    for path in paths.iter() {
+        match load(Path::new(path)) {
+            Ok(loaded) => brains.extend(loaded),
+            Err(e) => exit_with(&format!("Failed to load {}: {}", path, e)),
+        }
+    }
+    let reference = <SimBrain as Brain>::random(&config);
+    brains.retain(|(name, brain)| {
+        let is_compatible = brain.is_compatible(&reference);
+        if !is_compatible {
+            println!("Skipping {}, it doesn't fit the game config", name);
+        }
+        is_compatible
+    });
+    if brains.len() < 2 {
+        exit_with("A tournament needs at least 2 brains");
+    }
+
+    println!(
+        "{} brains, {:?}, {} games per match",
+        brains.len(),
+        mode,
+        num_games
+    );
+    let seeds = tournament::tournament_seeds(num_games);
+    let standings = tournament::run(&brains, &config, mode, &seeds);
+    println!("{}", tournament::to_table(&standings));
+
+    if let Some(path) = csv_path {
+        match fs::write(&path, tournament::to_csv(&standings)) {
+            Ok(_) => println!("Standings saved to {}", path),
+            Err(e) => exit_with(&format!("Failed to save the standings: {}", e)),
+        }
+    }
+}
+
+/// Every brain of a dir, or a single brain file, named after their path
+fn load(path: &Path) -> io::Result<Vec<(String, SimBrain)>> {
+    if path.is_dir() {
+        let brains = hall_of_fame::load_brain_files(path)?;
+        return Ok(brains
+            .into_iter()
+            .map(|(p, brain)| (p.display().to_string(), brain))
+            .collect());
+    }
+
+    let brain = <SimBrain as Brain>::from_text(&fs::read_to_string(path)?)?;
+    Ok(vec![(path.display().to_string(), brain)])
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,72 @@
+//! Brain
+//! Common interface of the genomes that can drive a snake
+//! Games, streams and the population are generic over it
+
+use std::io;
+
+use crate::game::GameConfig;
+use crate::nn::BatchNet;
+
+pub trait Brain: Clone {
+    /// Carried between the steps of a game, eg. the memory of recurrent layers
+    /// Starts from the default every game
+    type State: Clone + Default;
+
+    /// Random brain that can play games with this config
+    fn random(config: &GameConfig) -> Self;
+
+    fn num_inputs(&self) -> usize;
+
+    /// Output values, one per direction
+    fn predict(&self, inputs: &[f64], state: &mut Self::State) -> Vec<f64>;
+
+    /// Node values grouped in columns for drawing, inputs first and outputs last
+    /// The state is left untouched
+    fn layer_values(&self, inputs: &[f64], state: &Self::State) -> Vec<Vec<f64>>;
+
+    /// One flag per column after the inputs, true when the column feeds its values back
+    fn recurrent_layers(&self) -> Vec<bool> {
+        Vec::new()
+    }
+
+    /// `self` is the fitter parent
+    fn crossover(&self, other: &Self) -> Self;
+
+    fn mutate(&mut self, rate: f32, variation: f32);
+
+    /// Used for speciation, 0 for identical brains
+    fn distance(&self, other: &Self) -> f64;
+
+    /// True if both brains can be crossed over and compared
+    fn is_compatible(&self, other: &Self) -> bool;
+
+    fn to_text(&self) -> String;
+
+    fn from_text(text: &str) -> io::Result<Self>;
+
+    /// Flat parameter vector for the evolution strategies, None when not supported
+    fn params(&self) -> Option<Vec<f64>> {
+        None
+    }
+
+    /// Same shape as `self` with the given flat parameters
+    fn with_params(&self, _params: &[f64]) -> Option<Self> {
+        None
+    }
+
+    /// One step of supervised training from the default state, returns the loss
+    /// None when not supported
+    fn train_step(
+        &mut self,
+        _inputs: &[f64],
+        _targets: &[f64],
+        _learning_rate: f64,
+    ) -> Option<f64> {
+        None
+    }
+
+    /// Packs the brains for a single forward pass, None when batching isn't supported
+    fn batch(_brains: &[&Self]) -> Option<BatchNet> {
+        None
+    }
+}
@@ -1,16 +1,131 @@
 use macroquad::prelude::*;
 
+use crate::curriculum::CurriculumStage;
+use crate::es::Optimizer;
+use crate::game::{FitnessFn, VisionMode};
+use crate::level::Obstacles;
+use crate::migration::{MigrationTopology, ReplacementPolicy};
+use crate::nn::Net;
+use crate::pareto::Objective;
+use crate::start::{StartPosition, StartState};
+use crate::stream::{SeedFill, SelectionMode, StagnationResponse, StreamConfig};
+use crate::utils::FourDirs;
+
 // Game
 pub const GRID_W: i32 = 25;
 pub const GRID_H: i32 = 25;
+pub const GAME_VISION_MODE: VisionMode = VisionMode::FourDir;
+pub const GAME_FITNESS_FN: FitnessFn = FitnessFn::Exponential;
+pub const GAME_RECURRENT_BRAIN: bool = false;
+// eg. Obstacles::Scattered(0.05), Obstacles::Segments { count: 6, max_len: 8 }
+// or Obstacles::File("levels/rooms.txt")
+pub const GAME_OBSTACLES: Obstacles = Obstacles::None;
+// Toroidal board, snakes leaving an edge re-enter at the opposite edge
+pub const GAME_WRAP: bool = false;
+
+// Food
+// More items or any bonus or poison add the offset to the nearest item of each kind to the vision
+pub const FOOD_NUM_ITEMS: usize = 1;
+pub const FOOD_BONUS_CHANCE: f32 = 0.0;
+pub const FOOD_BONUS_STEPS: usize = 30;
+pub const FOOD_BONUS_GROWTH: usize = 3;
+pub const FOOD_NUM_POISON: usize = 0;
+pub const FOOD_POISON_SHRINK: usize = 1;
+
+// Start
+// Random positions and longer bodies keep brains from learning only the center spawn
+pub const START_POSITION: StartPosition = StartPosition::Center;
+pub const START_MIN_LENGTH: usize = 1;
+pub const START_MAX_LENGTH: usize = 1;
+// eg. Some(FourDirs::Right), None for a random direction
+pub const START_DIR: Option<FourDirs> = None;
+// eg. &[StartState { body: &[(5, 5), (4, 5), (3, 5)], dir: FourDirs::Right }]
+pub const START_PRESETS: &[StartState] = &[];
 
 // Sim
+// Brain of the simulated snakes, `Net` or the NEAT `Genome`
+pub type SimBrain = Net;
 pub const NUM_GAMES_PER_STREAM: usize = 1000;
 pub const NUM_STREAMS: usize = 1;
+// One entry per stream, NUM_STREAMS default streams are used when empty
+// eg. StreamConfig { mutation_rate: 0.2, ..StreamConfig::DEFAULT }
+pub const STREAM_CONFIGS: &[StreamConfig] = &[];
 pub const NUM_SIM_STEPS: usize = 100;
-pub const STREAM_REJUVENATION_PERCENT: f32 = 0.1;
-pub const STREAM_LOCAL_MAX_WAIT_SECS: f32 = 90.0;
 pub const SIM_SLEEP_MILLIS: u64 = 50;
+// One forward pass over all the snakes of a stream, with f32 weights
+// Validation and the hall of fame stay in f64, a near tie can pick another direction there
+pub const BATCH_INFERENCE_ENABLED: bool = true;
+
+// Arena
+// Snakes of a stream sharing a board and its food, 1 to play alone
+pub const ARENA_NUM_SNAKES: usize = 1;
+
+// Evolution strategies
+// Used instead of the genetic algorithm when set, only for brains with flat params
+pub const STREAM_OPTIMIZER: Optimizer = Optimizer::Genetic;
+pub const CMA_ES_INITIAL_SIGMA: f64 = 0.3;
+pub const OPENAI_ES_SIGMA: f64 = 0.1;
+pub const OPENAI_ES_LEARNING_RATE: f64 = 0.1;
+pub const JACOBI_MAX_SWEEPS: usize = 50;
+
+// Pretraining
+// Streams start from a brain trained on the demos of a scripted path-finding agent
+// Streams with the same kind of brain share one pretrained brain
+pub const PRETRAIN_ENABLED: bool = false;
+pub const PRETRAIN_DEMO_GAMES: usize = 20;
+pub const PRETRAIN_EPOCHS: usize = 10;
+pub const PRETRAIN_LEARNING_RATE: f64 = 0.2;
+// Demos recorded from human play with the [P] key, also used for pretraining when they exist
+pub const PRETRAIN_DEMOS_FILE: &str = "demos.txt";
+pub const HUMAN_PLAY_STEP_MILLIS: u64 = 150;
+
+// Curriculum
+// Stages from easiest to hardest, empty to always play with the game configs as they are
+// eg. CurriculumStage { grid_size: 10, obstacle_density: 0.0, starvation_steps: 50, advance_score: 5.0 }
+pub const CURRICULUM_STAGES: &[CurriculumStage] = &[];
+pub const CURRICULUM_FILE_NAME: &str = "curriculum.csv";
+
+// Seeding
+// Directory of saved brains to start from, eg. a hall of fame, None for random brains
+pub const SEED_BRAINS_DIR: Option<&str> = None;
+pub const SEED_FILL: SeedFill = SeedFill::Mutated;
+
+// Stagnation
+pub const STREAM_STAGNATION_GENS: usize = 20;
+pub const STREAM_STAGNATION_MIN_IMPROVEMENT: f32 = 0.01;
+pub const STREAM_STAGNATION_RESPONSES: &[StagnationResponse] = &[StagnationResponse::Migration];
+pub const STAGNATION_MUTATION_BOOST: f32 = 3.0;
+pub const STAGNATION_MUTATION_BOOST_GENS: usize = 5;
+pub const STAGNATION_RESTART_PERCENT: f32 = 0.5;
+
+// Migration
+pub const MIGRATION_TOPOLOGY: MigrationTopology = MigrationTopology::Ring;
+pub const MIGRATION_INTERVAL_GENS: usize = 10;
+pub const MIGRATION_NUM_MIGRANTS: usize = 10;
+pub const MIGRATION_REPLACEMENT: ReplacementPolicy = ReplacementPolicy::Worst;
+
+// Validation
+pub const VALIDATION_CANDIDATES: usize = 3;
+pub const VALIDATION_NUM_GAMES: usize = 20;
+pub const VALIDATION_SEED: u64 = 1_000_000;
+// Training games draw their seeds from here on, the held-out seeds stay below
+pub const TRAINING_SEED_START: u64 = 1 << 32;
+
+// Tournament
+pub const TOURNAMENT_NUM_GAMES: usize = 20;
+// Below the training seeds, far from the validation seeds
+pub const TOURNAMENT_SEED: u64 = 2_000_000;
+pub const ELO_INITIAL_RATING: f32 = 1000.0;
+pub const ELO_K_FACTOR: f32 = 16.0;
+// Elo is fitted to all the matches at once, passes stop once no rating moves more than the tolerance
+pub const ELO_MAX_PASSES: usize = 1000;
+pub const ELO_TOLERANCE: f32 = 0.01;
+
+// Hall of fame
+pub const HALL_OF_FAME_SIZE: usize = 10;
+// None to only save with the [S] key
+pub const HALL_OF_FAME_SAVE_INTERVAL_GENS: Option<usize> = None;
+pub const HALL_OF_FAME_DIR: &str = "hall_of_fame";
 
 // Pop
 pub const POP_NUM_RETAINED: f32 = 0.01;
@@ -29,3 +144,27 @@ pub const BRAIN_MUTATION_VARIATION: f32 = 0.1;
 pub const INP_LAYER_SIZE: usize = 12;
 pub const HIDDEN_LAYER_SIZE: usize = 8;
 pub const OUTPUT_LAYER_SIZE: usize = 4;
+
+// Selection
+pub const SELECTION_MODE: SelectionMode = SelectionMode::Fitness;
+pub const NOVELTY_WEIGHT: f32 = 0.5;
+pub const NOVELTY_K_NEAREST: usize = 15;
+pub const NOVELTY_ARCHIVE_ADD_PER_GEN: usize = 10;
+pub const NOVELTY_ARCHIVE_MAX_SIZE: usize = 500;
+pub const NOVELTY_GRID_BINS: usize = 5;
+pub const PARETO_OBJECTIVES: &[Objective] =
+    &[Objective::Score, Objective::Survival, Objective::Efficiency];
+
+// Speciation
+pub const SPECIATION_ENABLED: bool = false;
+pub const SPECIES_DISTANCE_THRESHOLD: f64 = 0.6;
+pub const SPECIES_TARGET_COUNT: usize = 10;
+pub const SPECIES_THRESHOLD_STEP: f64 = 0.02;
+
+// NEAT
+pub const NEAT_ADD_CONNECTION_RATE: f32 = 0.05;
+pub const NEAT_ADD_CONNECTION_TRIES: usize = 20;
+pub const NEAT_ADD_NODE_RATE: f32 = 0.03;
+pub const NEAT_EXCESS_COEFF: f64 = 1.0;
+pub const NEAT_DISJOINT_COEFF: f64 = 1.0;
+pub const NEAT_WEIGHT_COEFF: f64 = 0.4;
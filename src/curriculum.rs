@@ -0,0 +1,211 @@
+//! Curriculum
+//! Environment difficulty that grows with the population
+//! Moves to the next stage once the validation score meets the stage's threshold
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::game::GameConfig;
+use crate::level::Obstacles;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurriculumStage {
+    /// Width and height of the board
+    pub grid_size: i32,
+    pub obstacle_density: f32,
+    pub starvation_steps: usize,
+    /// Validation mean needed to move past this stage
+    pub advance_score: f32,
+}
+
+/// Smallest board of a stage, random cells are drawn inside the walls
+pub const MIN_GRID_SIZE: i32 = 4;
+
+pub struct Curriculum {
+    stages: Vec<CurriculumStage>,
+    stage: usize,
+}
+
+impl CurriculumStage {
+    /// Overrides the environment of the config, the brain settings are kept
+    pub fn apply(&self, config: &mut GameConfig) {
+        config.grid_w = self.grid_size;
+        config.grid_h = self.grid_size;
+        config.obstacles = if self.obstacle_density > 0.0 {
+            Obstacles::Scattered(self.obstacle_density)
+        } else {
+            Obstacles::None
+        };
+        config.level = None;
+        config.starvation_steps = self.starvation_steps;
+    }
+}
+
+impl Curriculum {
+    pub fn new() -> io::Result<Self> {
+        Self::with_stages(CURRICULUM_STAGES.to_vec())
+    }
+
+    /// Fails when a board is smaller than `MIN_GRID_SIZE`
+    pub fn with_stages(stages: Vec<CurriculumStage>) -> io::Result<Self> {
+        if let Some(stage) = stages.iter().find(|s| s.grid_size < MIN_GRID_SIZE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Curriculum grid size {} is below {}",
+                    stage.grid_size, MIN_GRID_SIZE
+                ),
+            ));
+        }
+
+        Ok(Self { stages, stage: 0 })
+    }
+
+    /// Index of the current stage, None without stages
+    pub fn stage(&self) -> Option<usize> {
+        (!self.stages.is_empty()).then_some(self.stage)
+    }
+
+    pub fn current(&self) -> Option<&CurriculumStage> {
+        self.stages.get(self.stage)
+    }
+
+    pub fn is_last_stage(&self) -> bool {
+        self.stage + 1 >= self.stages.len()
+    }
+
+    /// Moves to the next stage when the score meets the current threshold
+    /// Returns true when the stage changed
+    pub fn update(&mut self, score: f32) -> bool {
+        let Some(current) = self.current() else {
+            return false;
+        };
+        if self.is_last_stage() || score < current.advance_score {
+            return false;
+        }
+
+        self.stage += 1;
+        true
+    }
+
+    /// Writes the current stage next to a checkpoint, eg. the hall of fame
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let Some(current) = self.current() else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(dir)?;
+        let text = format!(
+            "stage,grid_size,obstacle_density,starvation_steps\n{},{},{},{}\n",
+            self.stage, current.grid_size, current.obstacle_density, current.starvation_steps
+        );
+        fs::write(dir.join(CURRICULUM_FILE_NAME), text)
+    }
+
+    /// Resumes from the stage saved in `dir`
+    /// Fails when the saved environment isn't the one of that stage, eg. after editing the stages
+    pub fn load(&mut self, dir: &Path) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let text = fs::read_to_string(dir.join(CURRICULUM_FILE_NAME))?;
+        let values: Vec<&str> = text
+            .lines()
+            .nth(1)
+            .ok_or_else(|| invalid("Missing curriculum stage"))?
+            .split(',')
+            .map(|v| v.trim())
+            .collect();
+        let [stage, grid_size, obstacle_density, starvation_steps] = values[..] else {
+            return Err(invalid("Invalid curriculum stage"));
+        };
+        let stage: usize = stage
+            .parse()
+            .map_err(|_| invalid("Invalid curriculum stage"))?;
+        let Some(current) = self.stages.get(stage) else {
+            return Err(invalid("Curriculum stage out of range"));
+        };
+
+        let is_same_stage = grid_size.parse() == Ok(current.grid_size)
+            && obstacle_density.parse() == Ok(current.obstacle_density)
+            && starvation_steps.parse() == Ok(current.starvation_steps);
+        if !is_same_stage {
+            return Err(invalid(
+                "Saved curriculum stage doesn't match the configured one",
+            ));
+        }
+
+        self.stage = stage;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stages() -> Vec<CurriculumStage> {
+        vec![
+            CurriculumStage {
+                grid_size: 10,
+                obstacle_density: 0.0,
+                starvation_steps: 50,
+                advance_score: 5.0,
+            },
+            CurriculumStage {
+                grid_size: 15,
+                obstacle_density: 0.05,
+                starvation_steps: 80,
+                advance_score: 10.0,
+            },
+        ]
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("snake_curriculum_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn load_resumes_the_saved_stage() {
+        let dir = temp_dir("resume");
+        let mut curriculum = Curriculum::with_stages(stages()).unwrap();
+        assert!(curriculum.update(5.0));
+        curriculum.save(&dir).unwrap();
+
+        let mut loaded = Curriculum::with_stages(stages()).unwrap();
+        loaded.load(&dir).unwrap();
+        assert_eq!(loaded.stage(), Some(1));
+    }
+
+    #[test]
+    fn load_rejects_a_different_stage() {
+        let dir = temp_dir("mismatch");
+        let mut curriculum = Curriculum::with_stages(stages()).unwrap();
+        curriculum.update(5.0);
+        curriculum.save(&dir).unwrap();
+
+        let mut edited = stages();
+        edited[1].obstacle_density = 0.1;
+        let mut loaded = Curriculum::with_stages(edited).unwrap();
+        assert!(loaded.load(&dir).is_err());
+        assert_eq!(loaded.stage(), Some(0));
+
+        let mut shorter = Curriculum::with_stages(stages()[..1].to_vec()).unwrap();
+        assert!(shorter.load(&dir).is_err());
+
+        fs::write(dir.join(CURRICULUM_FILE_NAME), "stage\n1\n").unwrap();
+        assert!(Curriculum::with_stages(stages())
+            .unwrap()
+            .load(&dir)
+            .is_err());
+    }
+
+    #[test]
+    fn with_stages_rejects_tiny_boards() {
+        let mut tiny = stages();
+        tiny[0].grid_size = 3;
+        assert!(Curriculum::with_stages(tiny).is_err());
+    }
+}
@@ -0,0 +1,513 @@
+//! Evolution Strategies
+//! Alternatives to the genetic algorithm that treat a brain as a flat parameter vector
+//! CMA-ES with a full covariance matrix and OpenAI-ES with antithetic sampling
+
+use std::io;
+
+use rand::Rng;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Optimizer {
+    /// Selection, crossover and mutation in `Stream::reset`
+    Genetic,
+    /// Covariance Matrix Adaptation, samples around a mean with a learned covariance
+    CmaEs,
+    /// Natural evolution strategy, follows the fitness gradient estimated from mirrored noise
+    OpenAiEs,
+}
+
+pub enum Strategy {
+    CmaEs(CmaEs),
+    OpenAiEs(OpenAiEs),
+}
+
+pub struct CmaEs {
+    mean: Vec<f64>,
+    sigma: f64,
+    cov: Vec<Vec<f64>>,
+    /// Eigenvectors of the covariance, one per column
+    eigen_vectors: Vec<Vec<f64>>,
+    /// Square roots of the eigenvalues
+    eigen_scales: Vec<f64>,
+    path_sigma: Vec<f64>,
+    path_cov: Vec<f64>,
+    generation: usize,
+    /// Generation of the last eigendecomposition
+    eigen_generation: usize,
+}
+
+pub struct OpenAiEs {
+    theta: Vec<f64>,
+}
+
+impl Strategy {
+    /// None for the genetic algorithm
+    pub fn new(optimizer: Optimizer, start: Vec<f64>) -> Option<Self> {
+        match optimizer {
+            Optimizer::Genetic => None,
+            Optimizer::CmaEs => Some(Self::CmaEs(CmaEs::new(start, CMA_ES_INITIAL_SIGMA))),
+            Optimizer::OpenAiEs => Some(Self::OpenAiEs(OpenAiEs::new(start))),
+        }
+    }
+
+    pub fn ask(&self, num_samples: usize) -> Vec<Vec<f64>> {
+        match self {
+            Self::CmaEs(es) => es.ask(num_samples),
+            Self::OpenAiEs(es) => es.ask(num_samples),
+        }
+    }
+
+    /// Updates the distribution from the evaluated samples, higher scores are better
+    /// Samples don't have to come from `ask`, eg. migrants from other streams
+    pub fn tell(&mut self, samples: &[Vec<f64>], scores: &[f32]) {
+        match self {
+            Self::CmaEs(es) => es.tell(samples, scores),
+            Self::OpenAiEs(es) => es.tell(samples, scores),
+        }
+    }
+
+    pub fn optimizer(&self) -> Optimizer {
+        match self {
+            Self::CmaEs(_) => Optimizer::CmaEs,
+            Self::OpenAiEs(_) => Optimizer::OpenAiEs,
+        }
+    }
+
+    pub fn num_params(&self) -> usize {
+        match self {
+            Self::CmaEs(es) => es.mean.len(),
+            Self::OpenAiEs(es) => es.theta.len(),
+        }
+    }
+
+    /// Plain text format, the first line has the optimizer and the number of params
+    /// CMA-ES follows with sigma and the generation, the mean, both evolution paths
+    /// and one line per covariance row, OpenAI-ES with theta
+    pub fn to_text(&self) -> String {
+        let line = |values: &[f64]| -> String {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            values.join(" ")
+        };
+
+        let mut lines = Vec::new();
+        match self {
+            Self::CmaEs(es) => {
+                lines.push(format!("cma-es {}", es.mean.len()));
+                lines.push(format!("{} {}", es.sigma, es.generation));
+                lines.push(line(&es.mean));
+                lines.push(line(&es.path_sigma));
+                lines.push(line(&es.path_cov));
+                lines.extend(es.cov.iter().map(|row| line(row)));
+            }
+            Self::OpenAiEs(es) => {
+                lines.push(format!("openai-es {}", es.theta.len()));
+                lines.push(line(&es.theta));
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let bad_data = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| bad_data("Missing optimizer"))?
+            .split_whitespace()
+            .collect();
+        let [kind, n] = header[..] else {
+            return Err(bad_data("Bad optimizer header"));
+        };
+        let n: usize = n.parse().map_err(|_| bad_data("Bad number of params"))?;
+
+        let mut next_values = |len: usize| -> io::Result<Vec<f64>> {
+            let values = lines
+                .next()
+                .ok_or_else(|| bad_data("Missing strategy values"))?
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| bad_data("Bad strategy values"))?;
+            if values.len() != len {
+                return Err(bad_data("Wrong number of strategy values"));
+            }
+            Ok(values)
+        };
+
+        match kind {
+            "cma-es" => {
+                let state = next_values(2)?;
+                let mut es = CmaEs::new(next_values(n)?, state[0]);
+                es.generation = state[1] as usize;
+                es.path_sigma = next_values(n)?;
+                es.path_cov = next_values(n)?;
+                for row in es.cov.iter_mut() {
+                    *row = next_values(n)?;
+                }
+                es.update_eigen();
+                Ok(Self::CmaEs(es))
+            }
+            "openai-es" => Ok(Self::OpenAiEs(OpenAiEs::new(next_values(n)?))),
+            _ => Err(bad_data("Unknown optimizer")),
+        }
+    }
+}
+
+/// File of a stream's strategy next to the hall of fame, not .txt so it isn't loaded as a brain
+pub fn strategy_file_name(stream: usize) -> String {
+    format!("strategy_{}.es", stream)
+}
+
+impl CmaEs {
+    pub fn new(mean: Vec<f64>, sigma: f64) -> Self {
+        let n = mean.len();
+        let mut identity = vec![vec![0.0; n]; n];
+        for (i, row) in identity.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+
+        Self {
+            mean,
+            sigma,
+            cov: identity.clone(),
+            eigen_vectors: identity,
+            eigen_scales: vec![1.0; n],
+            path_sigma: vec![0.0; n],
+            path_cov: vec![0.0; n],
+            generation: 0,
+            eigen_generation: 0,
+        }
+    }
+
+    /// mean + sigma * B * D * z
+    pub fn ask(&self, num_samples: usize) -> Vec<Vec<f64>> {
+        let mut rng = rand::thread_rng();
+        let n = self.mean.len();
+
+        (0..num_samples)
+            .map(|_| {
+                let scaled: Vec<f64> = (0..n)
+                    .map(|i| self.eigen_scales[i] * gaussian(&mut rng))
+                    .collect();
+                let y = mat_vec(&self.eigen_vectors, &scaled);
+                self.mean
+                    .iter()
+                    .zip(y.iter())
+                    .map(|(m, y)| m + self.sigma * y)
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn tell(&mut self, samples: &[Vec<f64>], scores: &[f32]) {
+        let n = self.mean.len() as f64;
+        let order = best_first(scores);
+        let num_parents = (samples.len() / 2).max(1);
+
+        // Recombination weights
+        let mut weights: Vec<f64> = (0..num_parents)
+            .map(|i| (num_parents as f64 + 0.5).ln() - ((i + 1) as f64).ln())
+            .collect();
+        let weights_sum: f64 = weights.iter().sum();
+        weights.iter_mut().for_each(|w| *w /= weights_sum);
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        // Learning rates
+        let c_sigma = (mu_eff + 2.0) / (n + mu_eff + 5.0);
+        let d_sigma = 1.0 + 2.0 * (((mu_eff - 1.0) / (n + 1.0)).sqrt() - 1.0).max(0.0) + c_sigma;
+        let c_c = (4.0 + mu_eff / n) / (n + 4.0 + 2.0 * mu_eff / n);
+        let c_1 = 2.0 / ((n + 1.3).powi(2) + mu_eff);
+        let c_mu =
+            (1.0 - c_1).min(2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((n + 2.0).powi(2) + mu_eff));
+        let chi_n = n.sqrt() * (1.0 - 1.0 / (4.0 * n) + 1.0 / (21.0 * n * n));
+
+        // Steps of the best samples, injected ones are clipped to a likely length
+        let max_norm = n.sqrt() + 2.0 * n / (n + 2.0);
+        let steps: Vec<Vec<f64>> = order
+            .iter()
+            .take(num_parents)
+            .map(|&idx| {
+                let mut y: Vec<f64> = samples[idx]
+                    .iter()
+                    .zip(self.mean.iter())
+                    .map(|(x, m)| (x - m) / self.sigma)
+                    .collect();
+                let norm = norm(&self.inv_sqrt_cov(&y));
+                if norm > max_norm {
+                    y.iter_mut().for_each(|v| *v *= max_norm / norm);
+                }
+                y
+            })
+            .collect();
+        let mut step = vec![0.0; self.mean.len()];
+        for (y, w) in steps.iter().zip(weights.iter()) {
+            for (s, v) in step.iter_mut().zip(y.iter()) {
+                *s += w * v;
+            }
+        }
+
+        for (m, s) in self.mean.iter_mut().zip(step.iter()) {
+            *m += self.sigma * s;
+        }
+
+        // Evolution paths
+        let whitened = self.inv_sqrt_cov(&step);
+        let sigma_rate = (c_sigma * (2.0 - c_sigma) * mu_eff).sqrt();
+        for (p, w) in self.path_sigma.iter_mut().zip(whitened.iter()) {
+            *p = (1.0 - c_sigma) * *p + sigma_rate * w;
+        }
+        self.generation += 1;
+        let path_norm = norm(&self.path_sigma);
+        let expected = (1.0 - (1.0 - c_sigma).powi(2 * self.generation as i32)).sqrt();
+        // Stops the covariance path while sigma is growing fast
+        let is_path_short = path_norm / expected < (1.4 + 2.0 / (n + 1.0)) * chi_n;
+        let h_sigma = if is_path_short { 1.0 } else { 0.0 };
+        let cov_rate = (c_c * (2.0 - c_c) * mu_eff).sqrt();
+        for (p, s) in self.path_cov.iter_mut().zip(step.iter()) {
+            *p = (1.0 - c_c) * *p + h_sigma * cov_rate * s;
+        }
+
+        // Covariance, rank one and rank mu updates
+        let decay = 1.0 - c_1 - c_mu + (1.0 - h_sigma) * c_1 * c_c * (2.0 - c_c);
+        for i in 0..self.cov.len() {
+            for j in 0..=i {
+                let mut rank_mu = 0.0;
+                for (y, w) in steps.iter().zip(weights.iter()) {
+                    rank_mu += w * y[i] * y[j];
+                }
+                let value = decay * self.cov[i][j]
+                    + c_1 * self.path_cov[i] * self.path_cov[j]
+                    + c_mu * rank_mu;
+                self.cov[i][j] = value;
+                self.cov[j][i] = value;
+            }
+        }
+
+        self.sigma *= ((c_sigma / d_sigma) * (path_norm / chi_n - 1.0)).exp();
+
+        // The decomposition is O(n^3), like standard CMA-ES it's only redone
+        // once the covariance has moved enough to matter
+        let eigen_interval = samples.len() as f64 / ((c_1 + c_mu) * n * 10.0);
+        if (self.generation - self.eigen_generation) as f64 >= eigen_interval {
+            self.update_eigen();
+        }
+    }
+
+    fn update_eigen(&mut self) {
+        let (eigen_values, eigen_vectors) = jacobi_eigen(&self.cov);
+        self.eigen_scales = eigen_values.iter().map(|v| v.max(1e-20).sqrt()).collect();
+        self.eigen_vectors = eigen_vectors;
+        self.eigen_generation = self.generation;
+    }
+
+    /// C^-1/2 * v = B * D^-1 * B^T * v
+    fn inv_sqrt_cov(&self, v: &[f64]) -> Vec<f64> {
+        let n = v.len();
+        let mut projected = vec![0.0; n];
+        for (j, p) in projected.iter_mut().enumerate() {
+            let dot: f64 = (0..n).map(|i| self.eigen_vectors[i][j] * v[i]).sum();
+            *p = dot / self.eigen_scales[j];
+        }
+
+        mat_vec(&self.eigen_vectors, &projected)
+    }
+}
+
+impl OpenAiEs {
+    pub fn new(theta: Vec<f64>) -> Self {
+        Self { theta }
+    }
+
+    /// Mirrored pairs theta + sigma * eps and theta - sigma * eps
+    /// An odd sample count also gets theta itself
+    pub fn ask(&self, num_samples: usize) -> Vec<Vec<f64>> {
+        let mut rng = rand::thread_rng();
+        let mut samples = Vec::with_capacity(num_samples);
+        for _ in 0..num_samples / 2 {
+            let noise: Vec<f64> = (0..self.theta.len()).map(|_| gaussian(&mut rng)).collect();
+            let sample = |sign: f64| -> Vec<f64> {
+                self.theta
+                    .iter()
+                    .zip(noise.iter())
+                    .map(|(t, e)| t + sign * OPENAI_ES_SIGMA * e)
+                    .collect()
+            };
+            samples.push(sample(1.0));
+            samples.push(sample(-1.0));
+        }
+        if num_samples % 2 == 1 {
+            samples.push(self.theta.clone());
+        }
+
+        samples
+    }
+
+    /// Gradient step with centered ranks as the fitness shaping
+    pub fn tell(&mut self, samples: &[Vec<f64>], scores: &[f32]) {
+        if samples.len() < 2 {
+            return;
+        }
+
+        let max_rank = (samples.len() - 1) as f64;
+        let mut shaped = vec![0.0; samples.len()];
+        for (rank, &idx) in best_first(scores).iter().rev().enumerate() {
+            shaped[idx] = rank as f64 / max_rank - 0.5;
+        }
+
+        let mut gradient = vec![0.0; self.theta.len()];
+        for (sample, f) in samples.iter().zip(shaped.iter()) {
+            for ((g, x), t) in gradient
+                .iter_mut()
+                .zip(sample.iter())
+                .zip(self.theta.iter())
+            {
+                *g += f * (x - t) / OPENAI_ES_SIGMA;
+            }
+        }
+
+        let scale = OPENAI_ES_LEARNING_RATE / (samples.len() as f64 * OPENAI_ES_SIGMA);
+        for (t, g) in self.theta.iter_mut().zip(gradient.iter()) {
+            *t += scale * g;
+        }
+    }
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, cyclic Jacobi rotations
+pub fn jacobi_eigen(matrix: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = vec![vec![0.0; n]; n];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+
+    for _ in 0..JACOBI_MAX_SWEEPS {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        if off_diagonal < 1e-22 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q].abs() < 1e-30 {
+                    continue;
+                }
+
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for row in a.iter_mut() {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let (upper, lower) = a.split_at_mut(q);
+                for (pk, qk) in upper[p].iter_mut().zip(lower[0].iter_mut()) {
+                    let (x, y) = (*pk, *qk);
+                    *pk = c * x - s * y;
+                    *qk = s * x + c * y;
+                }
+                for row in v.iter_mut() {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+            }
+        }
+    }
+
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+/// Indices sorted by score, highest first
+fn best_first(scores: &[f32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| {
+        scores[b]
+            .partial_cmp(&scores[a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    order
+}
+
+/// Standard normal sample, Box-Muller
+fn gaussian(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen_range(0.0..1.0);
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+fn mat_vec(matrix: &[Vec<f64>], v: &[f64]) -> Vec<f64> {
+    matrix
+        .iter()
+        .map(|row| row.iter().zip(v.iter()).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+fn norm(v: &[f64]) -> f64 {
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openai_es_tell_moves_towards_the_better_sample() {
+        let mut es = OpenAiEs::new(vec![0.0, 0.0]);
+        es.tell(&[vec![1.0, 0.0], vec![-1.0, 0.0]], &[1.0, 0.0]);
+        assert!(es.theta[0] > 0.0);
+        assert_eq!(es.theta[1], 0.0);
+    }
+
+    #[test]
+    fn cma_es_tell_moves_the_mean_towards_the_best_samples() {
+        let mut es = CmaEs::new(vec![0.0, 0.0], 1.0);
+        let samples = vec![
+            vec![1.0, 1.0],
+            vec![0.5, 1.0],
+            vec![-1.0, -1.0],
+            vec![-0.5, -1.0],
+        ];
+        es.tell(&samples, &[4.0, 3.0, 2.0, 1.0]);
+        assert!(es.mean[0] > 0.0 && es.mean[1] > 0.0);
+        assert_eq!(es.generation, 1);
+        assert!(es.cov[0][1] == es.cov[1][0]);
+    }
+
+    #[test]
+    fn text_round_trip_keeps_the_state() {
+        let mut cma = Strategy::new(Optimizer::CmaEs, vec![0.5, -0.25, 1.0]).unwrap();
+        let samples = cma.ask(6);
+        cma.tell(&samples, &[6.0, 5.0, 4.0, 3.0, 2.0, 1.0]);
+        let openai = Strategy::new(Optimizer::OpenAiEs, vec![0.5, -0.25]).unwrap();
+
+        for strategy in [cma, openai] {
+            let text = strategy.to_text();
+            let loaded = Strategy::from_text(&text).unwrap();
+            assert_eq!(loaded.optimizer(), strategy.optimizer());
+            assert_eq!(loaded.to_text(), text);
+        }
+        assert!(Strategy::from_text("cma-es 2\n1 0\n0 0\n").is_err());
+        assert!(Strategy::from_text("sgd 2\n0 0\n").is_err());
+    }
+
+    #[test]
+    fn jacobi_eigen_finds_the_eigenvalues() {
+        let (mut values, vectors) = jacobi_eigen(&[vec![2.0, 1.0], vec![1.0, 2.0]]);
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((values[0] - 1.0).abs() < 1e-9 && (values[1] - 3.0).abs() < 1e-9);
+        let dot = vectors[0][0] * vectors[0][1] + vectors[1][0] * vectors[1][1];
+        assert!(dot.abs() < 1e-9);
+    }
+}
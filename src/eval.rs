@@ -0,0 +1,73 @@
+//! Evaluation
+//! Plays a brain on seeded games to measure how it performs on average
+//! A single game is too noisy to compare brains
+
+use rand::Rng;
+
+use crate::brain::Brain;
+use crate::game::{Game, GameConfig};
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EvalResult {
+    pub mean: f32,
+    pub std: f32,
+}
+
+/// Plays one game per seed, the same seeds always give the same food positions
+pub fn evaluate_on_seeds<B: Brain>(net: &B, config: &GameConfig, seeds: &[u64]) -> EvalResult {
+    let mut scores = Vec::with_capacity(seeds.len());
+    for &seed in seeds {
+        let mut game = Game::<B>::with_seed(config, seed);
+        game.brain = net.clone();
+        scores.push(play(&mut game) as f32);
+    }
+
+    EvalResult::from_scores(&scores)
+}
+
+// Held-out seed ranges can't overlap the training seeds
+const _: () = assert!(VALIDATION_SEED + VALIDATION_NUM_GAMES as u64 <= TRAINING_SEED_START);
+const _: () = assert!(TOURNAMENT_SEED + TOURNAMENT_NUM_GAMES as u64 <= TRAINING_SEED_START);
+
+/// Held-out seeds, training games never use them
+pub fn validation_seeds() -> Vec<u64> {
+    (0..VALIDATION_NUM_GAMES as u64)
+        .map(|i| VALIDATION_SEED + i)
+        .collect()
+}
+
+/// Random seed of a training game, never one of the held-out seeds
+pub fn training_seed(rng: &mut impl Rng) -> u64 {
+    rng.gen_range(TRAINING_SEED_START..=u64::MAX)
+}
+
+pub fn validate<B: Brain>(net: &B, config: &GameConfig) -> EvalResult {
+    evaluate_on_seeds(net, config, &validation_seeds())
+}
+
+/// Updates the game until it's complete, returns the final score
+pub fn play<B: Brain>(game: &mut Game<B>) -> usize {
+    while !game.is_complete {
+        game.update();
+    }
+
+    game.score()
+}
+
+impl EvalResult {
+    pub fn from_scores(scores: &[f32]) -> Self {
+        if scores.is_empty() {
+            return Self::default();
+        }
+
+        let n = scores.len() as f32;
+        let mean = scores.iter().sum::<f32>() / n;
+        let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n;
+
+        Self {
+            mean,
+            std: variance.sqrt(),
+        }
+    }
+}
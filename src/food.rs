@@ -0,0 +1,85 @@
+//! Food
+//! Items the snake can eat, several can be on the board at once
+//! Bonus food grows the snake more but expires, poison shrinks it
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoodKind {
+    Normal,
+    /// Grows the snake by `bonus_growth`, disappears after `bonus_steps`
+    Bonus,
+    /// Shrinks the snake by `poison_shrink`, doesn't count as a meal
+    Poison,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Food {
+    pub pos: Point,
+    pub kind: FoodKind,
+    /// Steps left before it disappears, None for food that stays until eaten
+    pub expires_in: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FoodConfig {
+    /// Normal food on the board at once
+    pub num_food: usize,
+    /// Chance every step that a bonus food shows up when there's none
+    pub bonus_chance: f32,
+    pub bonus_steps: usize,
+    pub bonus_growth: usize,
+    /// Poison on the board at once, eaten poison shows up somewhere else
+    pub num_poison: usize,
+    pub poison_shrink: usize,
+}
+
+impl FoodKind {
+    pub const ALL: [Self; 3] = [Self::Normal, Self::Bonus, Self::Poison];
+
+    pub fn is_edible(&self) -> bool {
+        !matches!(self, Self::Poison)
+    }
+}
+
+impl Food {
+    pub fn new(pos: Point, kind: FoodKind) -> Self {
+        Self {
+            pos,
+            kind,
+            expires_in: None,
+        }
+    }
+}
+
+impl FoodConfig {
+    pub const DEFAULT: Self = Self {
+        num_food: FOOD_NUM_ITEMS,
+        bonus_chance: FOOD_BONUS_CHANCE,
+        bonus_steps: FOOD_BONUS_STEPS,
+        bonus_growth: FOOD_BONUS_GROWTH,
+        num_poison: FOOD_NUM_POISON,
+        poison_shrink: FOOD_POISON_SHRINK,
+    };
+
+    /// A single normal food, the original game
+    pub fn is_classic(&self) -> bool {
+        self.num_food <= 1 && self.bonus_chance <= 0.0 && self.num_poison == 0
+    }
+
+    /// Vision inputs added on top of the rays, the offset to the nearest item of each kind
+    /// 0 for the classic game so its brains keep their shape
+    pub fn num_inputs(&self) -> usize {
+        if self.is_classic() {
+            0
+        } else {
+            FoodKind::ALL.len() * 2
+        }
+    }
+}
+
+impl Default for FoodConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
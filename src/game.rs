@@ -1,38 +1,309 @@
 //! Snake Game Logic
 //! Snake Actions from a Neural Network
 
+use std::io;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::brain::Brain;
+use crate::eval;
+use crate::food::{Food, FoodConfig, FoodKind};
+use crate::level::{self, Level, Obstacles};
 use crate::nn::Net;
+use crate::start::StartConfig;
 use crate::*;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VisionMode {
+    /// Wall, food and body in the 4 straight directions
+    FourDir,
+    /// Wall, food and body in the 4 straight and 4 diagonal directions
+    EightDir,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FitnessFn {
+    /// Grows exponentially with the score and linearly with the steps
+    Exponential,
+    /// Square of the score, steps are ignored
+    Quadratic,
+    /// Square of the score times the steps
+    ScoreAndSurvival,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TerminationReason {
+    Wall,
+    Body,
+    /// No food for too many steps
+    Starvation,
+    /// Shrunk to nothing by poison
+    Poison,
+}
+
+/// Games by state, derived from the games themselves
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GameCounts {
+    pub alive: usize,
+    pub completed: usize,
+    pub wall: usize,
+    pub body: usize,
+    pub starvation: usize,
+    pub poison: usize,
+}
+
+/// Snapshot of a game, everything an outside agent or analysis tool can see
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameState {
+    pub grid_w: i32,
+    pub grid_h: i32,
+    pub wrap: bool,
+    /// Head first
+    pub body: Vec<Point>,
+    pub food: Vec<Food>,
+    /// Walls inside the border
+    pub obstacles: Vec<Point>,
+    /// Cells of the other snakes in an arena
+    pub opponents: Vec<Point>,
+    pub dir: FourDirs,
+    pub num_steps: usize,
+    pub no_food_steps: usize,
+    pub num_food_eaten: usize,
+    pub score: usize,
+    /// None while the game is running
+    pub termination: Option<TerminationReason>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GameConfig {
+    pub vision: VisionMode,
+    pub fitness: FitnessFn,
+    /// Brains with recurrent hidden layers that remember past steps
+    pub recurrent: bool,
+    /// Position of the right and bottom walls, the left and top walls are at 0
+    pub grid_w: i32,
+    pub grid_h: i32,
+    /// Walls inside the board, a level file also sets the grid size
+    pub obstacles: Obstacles,
+    /// Set from the level file of `obstacles` by `load_level`, games ignore the file until then
+    pub level: Option<Arc<Level>>,
+    /// Toroidal board, leaving an edge re-enters at the opposite edge
+    pub wrap: bool,
+    pub food: FoodConfig,
+    /// Spawn position, length and direction of the snake
+    pub start: StartConfig,
+    /// Steps without food before starving, more are allowed for longer snakes
+    pub starvation_steps: usize,
+}
+
 #[derive(Clone)]
-pub struct Game {
+pub struct Game<B: Brain = Net> {
     pub head: Point,
     pub body: Vec<Point>,
-    pub food: Point,
+    /// Every item on the board, the first normal food is always there
+    pub food: Vec<Food>,
     pub dir: FourDirs,
-    pub brain: Net,
+    pub brain: B,
+    pub config: GameConfig,
+    rng: StdRng,
 
     pub is_complete: bool,
+    termination: Option<TerminationReason>,
     no_food_steps: usize,
+    num_food_eaten: usize,
     num_steps: usize,
+    /// Length of the body at the start, the score only counts what was grown since
+    start_len: usize,
+    num_left_turns: usize,
+    num_right_turns: usize,
+    visits: Vec<usize>,
+    /// Obstacle cells, indexed by `cell_index`
+    obstacles: Vec<bool>,
+    /// Cells of the other snakes sharing the board in an arena
+    opponents: Vec<Point>,
+    /// Hidden state of the recurrent layers, starts zeroed every episode
+    memory: B::State,
 }
 
-impl Game {
+impl VisionMode {
+    pub fn num_inputs(&self) -> usize {
+        match self {
+            Self::FourDir => 4 * 3,
+            Self::EightDir => 8 * 3,
+        }
+    }
+}
+
+impl GameCounts {
+    pub fn from_games<B: Brain>(games: &[Game<B>]) -> Self {
+        let mut counts = Self::default();
+        for game in games.iter() {
+            if !game.is_complete {
+                counts.alive += 1;
+                continue;
+            }
+
+            counts.completed += 1;
+            match game.termination {
+                Some(TerminationReason::Wall) => counts.wall += 1,
+                Some(TerminationReason::Body) => counts.body += 1,
+                Some(TerminationReason::Starvation) => counts.starvation += 1,
+                Some(TerminationReason::Poison) => counts.poison += 1,
+                None => {}
+            }
+        }
+
+        counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.alive + self.completed
+    }
+}
+
+impl std::ops::AddAssign for GameCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.alive += other.alive;
+        self.completed += other.completed;
+        self.wall += other.wall;
+        self.body += other.body;
+        self.starvation += other.starvation;
+        self.poison += other.poison;
+    }
+}
+
+impl GameConfig {
+    pub const DEFAULT: Self = Self {
+        vision: GAME_VISION_MODE,
+        fitness: GAME_FITNESS_FN,
+        recurrent: GAME_RECURRENT_BRAIN,
+        grid_w: GRID_W,
+        grid_h: GRID_H,
+        obstacles: GAME_OBSTACLES,
+        level: None,
+        wrap: GAME_WRAP,
+        starvation_steps: NUM_SIM_STEPS,
+        food: FoodConfig::DEFAULT,
+        start: StartConfig::DEFAULT,
+    };
+
+    /// Reads the level file of `obstacles` and takes its grid size
+    pub fn load_level(&mut self) -> io::Result<()> {
+        self.level = self.obstacles.load_level()?;
+        if let Some(level) = &self.level {
+            self.grid_w = level.grid_w;
+            self.grid_h = level.grid_h;
+        }
+        Ok(())
+    }
+
+    /// Size of the brain inputs, the rays and the nearest food of each kind
+    pub fn num_inputs(&self) -> usize {
+        self.vision.num_inputs() + self.food.num_inputs()
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl<B: Brain> Game<B> {
     pub fn new() -> Self {
-        let mut body = Vec::new();
-        let head = Point::new(GRID_W / 2, GRID_H / 2);
-        body.push(head.clone());
+        Self::with_config(&GameConfig::default())
+    }
+
+    pub fn with_config(config: &GameConfig) -> Self {
+        Self::with_seed(config, eval::training_seed(&mut rand::thread_rng()))
+    }
+
+    /// Same seed, same start, food positions and random obstacles
+    pub fn with_seed(config: &GameConfig, seed: u64) -> Self {
+        Self::with_reserved_cells(config, seed, &[])
+    }
 
-        Self {
+    /// Same as `with_seed`, the obstacles and food are kept off the `reserved` cells
+    /// eg. the spawns of the other snakes of an arena
+    pub fn with_reserved_cells(config: &GameConfig, seed: u64, reserved: &[Point]) -> Self {
+        let config = config.clone();
+        let level = config.level.clone();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let is_level_wall = |pt: Point| {
+            level
+                .as_ref()
+                .is_some_and(|level| level.cells()[level::cell_index(level.grid_w, pt)])
+        };
+        let (body, dir) = config
+            .start
+            .generate(config.grid_w, config.grid_h, &mut rng, |pt| {
+                !is_level_wall(pt)
+            });
+        let head = body[0];
+        let start_len = body.len();
+        let food = Point::rand(&mut rng, config.grid_w, config.grid_h);
+        let obstacles = match &level {
+            Some(level) => level.cells().to_vec(),
+            None => {
+                // The spawn area is kept clear around the head and every reserved cell
+                let free: Vec<Point> = body.iter().copied().chain([food]).collect();
+                let spawns: Vec<Point> = [head].iter().chain(reserved.iter()).copied().collect();
+                config
+                    .obstacles
+                    .generate(config.grid_w, config.grid_h, &mut rng, &free, &spawns)
+            }
+        };
+
+        let mut game = Self {
             body,
             head,
-            food: Point::rand(),
-            dir: FourDirs::get_rand_dir(),
-            brain: Net::new(),
+            food: vec![Food::new(food, FoodKind::Normal)],
+            dir,
+            brain: B::random(&config),
+            config,
+            rng,
             is_complete: false,
+            termination: None,
             no_food_steps: 0,
+            num_food_eaten: 0,
             num_steps: 0,
+            start_len,
+            num_left_turns: 0,
+            num_right_turns: 0,
+            visits: vec![0; NOVELTY_GRID_BINS * NOVELTY_GRID_BINS],
+            obstacles,
+            // Food is placed around the reserved cells like around other snakes
+            opponents: reserved.to_vec(),
+            memory: B::State::default(),
+        };
+
+        // Level walls, longer bodies and reserved cells aren't avoided when placing the first food
+        if game.is_obstacle(food) || game.body.contains(&food) || game.is_opponent(food) {
+            game.food[0].pos = game.get_random_empty_pos();
+        }
+        for _ in 1..game.config.food.num_food {
+            game.add_food(FoodKind::Normal);
+        }
+        for _ in 0..game.config.food.num_poison {
+            game.add_food(FoodKind::Poison);
         }
+        game.opponents.clear();
+        game
+    }
+
+    /// Food, poison and bonus food placed from now on come from this seed
+    /// eg. so the snakes of an arena don't respawn food on the same cells
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Moves the snake to a single cell start at `head`, eg. the spawn of an arena
+    pub fn respawn(&mut self, head: Point) {
+        self.head = head;
+        self.body = vec![head];
+        self.start_len = 1;
     }
 
     pub fn update(&mut self) {
@@ -40,84 +311,221 @@ impl Game {
             return;
         }
 
+        let dir = self.next_dir();
+        self.update_with_dir(dir);
+    }
+
+    /// Direction picked by the brain for the current state, steps the recurrent memory
+    pub fn next_dir(&mut self) -> FourDirs {
+        let vision = self.observe();
+        let nn_out = self.brain.predict(&vision, &mut self.memory);
+        Self::output_to_dir(&nn_out)
+    }
+
+    /// Steps the game with a brain output computed elsewhere, eg. by a batched forward pass
+    /// The memory of recurrent brains isn't updated, the output doesn't carry their new state
+    /// Brains with memory can't be batched and step with `update` instead
+    pub fn update_with_output(&mut self, nn_out: &[f64]) {
+        let dir = Self::output_to_dir(nn_out);
+        self.update_with_dir(dir);
+    }
+
+    /// Steps the game in the given direction, eg. from a scripted agent
+    /// Turning back onto the body keeps the current direction
+    pub fn update_with_dir(&mut self, dir: FourDirs) {
+        self.step(dir);
+        self.update_bonus_food();
+    }
+
+    /// Moves without updating the bonus food, an arena updates it once for all its snakes
+    pub fn step(&mut self, mut dir: FourDirs) {
+        if self.is_complete {
+            return;
+        }
+
+        if self.dir.is_horizontal() && dir.is_horizontal() && self.dir != dir {
+            dir = self.dir;
+        }
+        if self.dir.is_vertical() && dir.is_vertical() && self.dir != dir {
+            dir = self.dir;
+        }
+
         self.num_steps += 1;
-        self.dir = self.get_brain_output();
+        let prev_dir = self.dir;
+        self.dir = dir;
+        self.track_turn(prev_dir);
         self.handle_food_collision();
         self.update_snake_positions();
+        self.track_visit();
+        if self.is_wall(self.head) {
+            self.complete(TerminationReason::Wall);
+        }
+        if self.is_snake_body(self.head) || self.is_opponent(self.head) {
+            self.complete(TerminationReason::Body);
+        }
+        // After the collisions, a crash on the last allowed step isn't counted as starvation
         self.handle_step_limit();
-        if self.is_wall(self.head) || self.is_snake_body(self.head) {
-            self.is_complete = true;
+    }
+
+    pub fn termination(&self) -> Option<TerminationReason> {
+        self.termination
+    }
+
+    pub fn state(&self) -> GameState {
+        let (w, h) = (self.config.grid_w, self.config.grid_h);
+        let obstacles = (1..h)
+            .flat_map(|y| (1..w).map(move |x| Point::new(x, y)))
+            .filter(|&pt| self.is_obstacle(pt))
+            .collect();
+
+        GameState {
+            grid_w: w,
+            grid_h: h,
+            wrap: self.config.wrap,
+            body: self.body.clone(),
+            food: self.food.clone(),
+            obstacles,
+            opponents: self.opponents.clone(),
+            dir: self.dir,
+            num_steps: self.num_steps,
+            no_food_steps: self.no_food_steps,
+            num_food_eaten: self.num_food_eaten,
+            score: self.score(),
+            termination: self.termination,
+        }
+    }
+
+    fn complete(&mut self, reason: TerminationReason) {
+        if self.is_complete {
+            return;
         }
+
+        self.is_complete = true;
+        self.termination = Some(reason);
     }
 
+    /// Values of every layer for the current state, the memory is left untouched
     pub fn get_net_output(&self) -> Vec<Vec<f64>> {
-        let vision = self.get_snake_vision();
-        self.brain.predict(&vision)
+        let vision = self.observe();
+        self.brain.layer_values(&vision, &self.memory)
     }
 
-    fn get_brain_output(&self) -> FourDirs {
-        let vision = self.get_snake_vision();
-        let nn_out = self.brain.predict(&vision).pop().unwrap();
+    fn output_to_dir(nn_out: &[f64]) -> FourDirs {
         let max_index = nn_out
             .iter()
             .enumerate()
             .max_by(|(_, &a), (_, &b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal))
             .map(|(i, _)| i)
             .unwrap();
-        let mut dir = match max_index {
+        match max_index {
             0 => FourDirs::Left,
             1 => FourDirs::Right,
             2 => FourDirs::Bottom,
             _ => FourDirs::Top,
-        };
-
-        if self.dir.is_horizontal() {
-            if dir.is_horizontal() && self.dir != dir {
-                dir = self.dir;
-            }
-        }
-        if self.dir.is_vertical() {
-            if dir.is_vertical() && self.dir != dir {
-                dir = self.dir;
-            }
         }
-
-        dir
     }
 
-    fn get_snake_vision(&self) -> Vec<f64> {
-        // self.get_11_vision()
-        // self.get_custom_vision()
-        // self.get_eight_dir_vision()
-        self.get_four_dir_vision()
+    /// Exact inputs of the brain for the current state, for analysis tools or other agents
+    pub fn observe(&self) -> Vec<f64> {
+        match self.config.vision {
+            VisionMode::FourDir => self.get_dirs_vision(&FourDirs::get_all_dirs()),
+            VisionMode::EightDir => self.get_dirs_vision(&[
+                (-1, 0),
+                (1, 0),
+                (0, 1),
+                (0, -1),
+                (-1, -1),
+                (1, -1),
+                (-1, 1),
+                (1, 1),
+            ]),
+        }
     }
 
-    fn get_four_dir_vision(&self) -> Vec<f64> {
+    fn get_dirs_vision(&self, dirs: &[(i32, i32)]) -> Vec<f64> {
         let mut vision = Vec::new();
-        let dirs = FourDirs::get_all_dirs();
 
-        for d in dirs {
+        for &d in dirs {
             let (wall, food, body) = self.look_in_dir(self.head, d);
             vision.push(wall as f64);
             vision.push(if food { 1.0 } else { 0.0 });
             vision.push(body as f64);
         }
 
+        if self.config.food.num_inputs() > 0 {
+            for kind in FoodKind::ALL {
+                let (dx, dy) = self
+                    .nearest_food(kind)
+                    .map(|pt| self.offset_to(pt))
+                    .unwrap_or((0, 0));
+                vision.push(dx as f64 / self.config.grid_w as f64);
+                vision.push(dy as f64 / self.config.grid_h as f64);
+            }
+        }
+
         vision
     }
 
+    /// Closest item of the kind in steps, ignoring walls and the body
+    pub fn nearest_food(&self, kind: FoodKind) -> Option<Point> {
+        self.food
+            .iter()
+            .filter(|f| f.kind == kind)
+            .map(|f| f.pos)
+            .min_by_key(|&pt| {
+                let (dx, dy) = self.offset_to(pt);
+                dx.abs() + dy.abs()
+            })
+    }
+
+    /// Offset from the head, the short way around on a toroidal board
+    fn offset_to(&self, pt: Point) -> (i32, i32) {
+        let wrap = |d: i32, size: i32| {
+            let n = size - 1;
+            match d {
+                d if self.config.wrap && d > n / 2 => d - n,
+                d if self.config.wrap && d < -n / 2 => d + n,
+                d => d,
+            }
+        };
+
+        (
+            wrap(pt.x - self.head.x, self.config.grid_w),
+            wrap(pt.y - self.head.y, self.config.grid_h),
+        )
+    }
+
+    /// Same as `fitness`, values that overflowed f32 count as the highest
+    pub fn finite_fitness(&self) -> f32 {
+        let fitness = self.fitness();
+        if fitness.is_finite() {
+            fitness
+        } else {
+            f32::MAX
+        }
+    }
+
     pub fn fitness(&self) -> f32 {
-        let score = self.body.len() as f32;
+        let score = self.score() as f32;
+        match self.config.fitness {
+            FitnessFn::Exponential => self.exponential_fitness(),
+            FitnessFn::Quadratic => score.powi(2),
+            FitnessFn::ScoreAndSurvival => score.powi(2) * self.num_steps.max(1) as f32,
+        }
+    }
+
+    fn exponential_fitness(&self) -> f32 {
+        let score = self.score() as f32;
         if score <= 1.0 {
             return 1.0;
         }
 
         if score < 5.0 {
-            return (self.num_steps as f32 * 0.1) * (2.0 as f32).powf(score) * score;
+            return (self.num_steps as f32 * 0.1) * 2.0_f32.powf(score) * score;
         }
 
         let mut fitness = 1.0;
-        fitness *= (2.0 as f32).powf(score) * score;
+        fitness *= 2.0_f32.powf(score) * score;
         fitness *= self.num_steps as f32;
 
         // TODO f32 shouldn't work as it can't hold such a big value
@@ -125,15 +533,70 @@ impl Game {
         fitness
     }
 
+    /// Behaviour descriptor used by novelty search
+    /// Visited cells histogram, final head position and turn counts, all in 0..1
+    pub fn behaviour(&self) -> Vec<f32> {
+        let num_steps = self.num_steps.max(1) as f32;
+        let mut behaviour: Vec<f32> = self.visits.iter().map(|&v| v as f32 / num_steps).collect();
+
+        behaviour.push(self.head.x as f32 / self.config.grid_w as f32);
+        behaviour.push(self.head.y as f32 / self.config.grid_h as f32);
+        behaviour.push(self.num_left_turns as f32 / num_steps);
+        behaviour.push(self.num_right_turns as f32 / num_steps);
+
+        behaviour
+    }
+
+    /// Length grown since the start plus one, the length of the snake for single cell starts
+    /// so longer random starts don't score higher
     pub fn score(&self) -> usize {
-        self.body.len()
+        (self.body.len() + 1).saturating_sub(self.start_len)
+    }
+
+    pub fn num_steps(&self) -> usize {
+        self.num_steps
+    }
+
+    /// Normal and bonus food eaten, poison doesn't count
+    pub fn num_food_eaten(&self) -> usize {
+        self.num_food_eaten
     }
 
     pub fn is_wall(&self, pt: Point) -> bool {
-        pt.x >= GRID_W || pt.x <= 0 || pt.y >= GRID_H || pt.y <= 0
+        let pt = self.wrap(pt);
+        let (w, h) = (self.config.grid_w, self.config.grid_h);
+        if pt.x >= w || pt.x <= 0 || pt.y >= h || pt.y <= 0 {
+            return true;
+        }
+
+        self.is_obstacle(pt)
+    }
+
+    /// Same cell on a toroidal board, unchanged when the board has walls
+    pub fn wrap(&self, pt: Point) -> Point {
+        if !self.config.wrap {
+            return pt;
+        }
+
+        let (w, h) = (self.config.grid_w, self.config.grid_h);
+        Point::new(
+            (pt.x - 1).rem_euclid(w - 1) + 1,
+            (pt.y - 1).rem_euclid(h - 1) + 1,
+        )
+    }
+
+    /// Wall inside the board, from the level or the obstacle generator
+    pub fn is_obstacle(&self, pt: Point) -> bool {
+        let (w, h) = (self.config.grid_w, self.config.grid_h);
+        if pt.x >= w || pt.x <= 0 || pt.y >= h || pt.y <= 0 {
+            return false;
+        }
+
+        self.obstacles[level::cell_index(w, pt)]
     }
 
     pub fn is_snake_body(&self, pt: Point) -> bool {
+        let pt = self.wrap(pt);
         for p in self.body.iter().skip(1) {
             if pt == *p {
                 return true;
@@ -143,19 +606,62 @@ impl Game {
         false
     }
 
+    /// Cells of the other snakes, they block the way and the vision like the own body
+    pub fn set_opponents(&mut self, cells: Vec<Point>) {
+        self.opponents = cells;
+    }
+
+    /// Ends the game when the head is on an opponent, eg. once all the snakes of an arena moved
+    /// A crash on the last allowed step isn't counted as starvation, like in `step`
+    pub fn handle_opponent_collision(&mut self) {
+        let is_running =
+            !self.is_complete || self.termination == Some(TerminationReason::Starvation);
+        if is_running && self.is_opponent(self.head) {
+            self.is_complete = true;
+            self.termination = Some(TerminationReason::Body);
+        }
+    }
+
+    pub fn is_opponent(&self, pt: Point) -> bool {
+        let pt = self.wrap(pt);
+        self.opponents.contains(&pt)
+    }
+
     fn update_snake_positions(&mut self) {
         self.head.x += self.dir.value().0;
         self.head.y += self.dir.value().1;
+        self.head = self.wrap(self.head);
 
-        let mut prev_pos = self.head.clone();
+        let mut prev_pos = self.head;
         for p in self.body.iter_mut() {
-            let new_pos = *p;
-            *p = prev_pos;
-            prev_pos = new_pos;
+            std::mem::swap(p, &mut prev_pos);
+        }
+    }
+
+    fn track_turn(&mut self, prev_dir: FourDirs) {
+        let (px, py) = prev_dir.value();
+        let (nx, ny) = self.dir.value();
+
+        // Sign of the cross product, y grows towards the bottom
+        match px * ny - py * nx {
+            c if c < 0 => self.num_left_turns += 1,
+            c if c > 0 => self.num_right_turns += 1,
+            _ => {}
         }
     }
 
-    pub fn with_brain(new_brain: &Net) -> Self {
+    fn track_visit(&mut self) {
+        let bin = |v: i32, size: i32| {
+            let bin = (v.clamp(0, size) as usize * NOVELTY_GRID_BINS) / (size as usize + 1);
+            bin.min(NOVELTY_GRID_BINS - 1)
+        };
+
+        let idx = bin(self.head.y, self.config.grid_h) * NOVELTY_GRID_BINS
+            + bin(self.head.x, self.config.grid_w);
+        self.visits[idx] += 1;
+    }
+
+    pub fn with_brain(new_brain: &B) -> Self {
         let mut new_game = Self::new();
         new_game.brain = new_brain.clone();
 
@@ -163,39 +669,102 @@ impl Game {
     }
 
     fn handle_food_collision(&mut self) {
-        if self.head != self.food {
+        let Some(idx) = self.food.iter().position(|f| f.pos == self.head) else {
             self.no_food_steps += 1;
             return;
+        };
+
+        let food = self.food.remove(idx);
+        match food.kind {
+            FoodKind::Normal => {
+                self.grow(1);
+                self.add_food(FoodKind::Normal);
+            }
+            FoodKind::Bonus => self.grow(self.config.food.bonus_growth),
+            FoodKind::Poison => {
+                self.no_food_steps += 1;
+                self.add_food(FoodKind::Poison);
+                self.shrink(self.config.food.poison_shrink);
+                return;
+            }
         }
 
-        self.body.push(Point::new(self.head.x, self.head.y));
-        self.food = self.get_random_empty_pos();
+        self.num_food_eaten += 1;
         self.no_food_steps = 0;
     }
 
+    /// New segments start on the head and unfold as the snake moves
+    fn grow(&mut self, num_segments: usize) {
+        for _ in 0..num_segments {
+            self.body.push(Point::new(self.head.x, self.head.y));
+        }
+    }
+
+    fn shrink(&mut self, num_segments: usize) {
+        if self.body.len() <= num_segments {
+            self.complete(TerminationReason::Poison);
+            return;
+        }
+
+        self.body.truncate(self.body.len() - num_segments);
+    }
+
+    fn add_food(&mut self, kind: FoodKind) {
+        let mut food = Food::new(self.get_random_empty_pos(), kind);
+        if kind == FoodKind::Bonus {
+            food.expires_in = Some(self.config.food.bonus_steps);
+        }
+        self.food.push(food);
+    }
+
+    /// Expires the bonus food and sometimes adds a new one
+    pub fn update_bonus_food(&mut self) {
+        if self.is_complete {
+            return;
+        }
+
+        for food in self.food.iter_mut() {
+            if let Some(steps) = food.expires_in.as_mut() {
+                *steps = steps.saturating_sub(1);
+            }
+        }
+        self.food.retain(|f| f.expires_in != Some(0));
+
+        let chance = self.config.food.bonus_chance;
+        let has_bonus = self.food.iter().any(|f| f.kind == FoodKind::Bonus);
+        if chance > 0.0 && !has_bonus && self.rng.gen::<f32>() < chance {
+            self.add_food(FoodKind::Bonus);
+        }
+    }
+
     fn handle_step_limit(&mut self) {
+        let steps = self.config.starvation_steps;
         let limit = match self.score() {
-            score if score > 10 => NUM_SIM_STEPS * 2,
-            score if score > 20 => NUM_SIM_STEPS * 3,
-            score if score > 30 => NUM_SIM_STEPS * 5,
-            score if score > 80 => NUM_SIM_STEPS * 8,
-            _ => NUM_SIM_STEPS,
+            score if score > 10 => steps * 2,
+            score if score > 20 => steps * 3,
+            score if score > 30 => steps * 5,
+            score if score > 80 => steps * 8,
+            _ => steps,
         };
 
         if self.no_food_steps >= limit {
-            self.is_complete = true;
+            self.complete(TerminationReason::Starvation);
         }
     }
 
-    fn get_random_empty_pos(&self) -> Point {
-        let mut pt = Point::rand();
+    fn get_random_empty_pos(&mut self) -> Point {
+        let (w, h) = (self.config.grid_w, self.config.grid_h);
+        let mut pt = Point::rand(&mut self.rng, w, h);
 
+        // Obstacles can cover a good part of the board
         let mut num_tries = 0;
-        while num_tries < 5 {
+        while num_tries < 20 {
             num_tries += 1;
-            pt = Point::rand();
+            pt = Point::rand(&mut self.rng, w, h);
 
-            if !self.body.contains(&pt) {
+            let is_food = self.food.iter().any(|f| f.pos == pt);
+            let is_taken = self.body.contains(&pt) || self.is_opponent(pt);
+            if !is_taken && !self.is_wall(pt) && !is_food {
                 break;
             }
         }
@@ -214,19 +783,24 @@ impl Game {
                 break;
             }
 
-            if self.food == temp_pt {
+            if self
+                .food
+                .iter()
+                .any(|f| f.pos == temp_pt && f.kind.is_edible())
+            {
                 food = true;
             }
 
-            if self.is_snake_body(temp_pt) {
+            if self.is_snake_body(temp_pt) || self.is_opponent(temp_pt) {
                 // body = true;
                 break;
             }
 
-            temp_pt = Point::new(temp_pt.x + dir.0, temp_pt.y + dir.1);
+            temp_pt = self.wrap(Point::new(temp_pt.x + dir.0, temp_pt.y + dir.1));
 
             dist += 1;
-            if dist > 1000 {
+            // Rays on a toroidal board stop once they are back where they started
+            if dist > 1000 || temp_pt == st {
                 break;
             }
         }
@@ -235,8 +809,8 @@ impl Game {
     }
 
     pub fn render(&self) {
-        for x in 0..=GRID_W {
-            for y in 0..=GRID_H {
+        for x in 0..=self.config.grid_w {
+            for y in 0..=self.config.grid_h {
                 let pt = (x, y).into();
                 if self.is_wall(pt) {
                     print!("□");
@@ -249,7 +823,7 @@ impl Game {
                 if self.head == pt {
                     print!("■");
                 }
-                if self.food == pt {
+                if self.food.iter().any(|f| f.pos == pt) {
                     print!("●");
                 }
                 print!(".");
@@ -260,14 +834,361 @@ impl Game {
     }
 }
 
-impl PartialEq for Game {
+impl<B: Brain> Default for Game<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Brain> PartialEq for Game<B> {
     fn eq(&self, other: &Self) -> bool {
         self.fitness() == other.fitness()
     }
 }
 
-impl PartialOrd for Game {
+impl<B: Brain> PartialOrd for Game<B> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         self.fitness().partial_cmp(&other.fitness())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagonal_rays_cover_non_square_toroidal_boards() {
+        // 4 by 3 inner cells, a diagonal ray is back at the head after lcm(4, 3) steps
+        let config = GameConfig {
+            grid_w: 5,
+            grid_h: 4,
+            wrap: true,
+            obstacles: Obstacles::None,
+            ..GameConfig::DEFAULT
+        };
+        let mut game: Game = Game::with_seed(&config, 0);
+        game.body = vec![game.head];
+        let food = game.wrap(Point::new(game.head.x + 5, game.head.y + 5));
+        game.food = vec![Food::new(food, FoodKind::Normal)];
+
+        let (wall, is_food, _) = game.look_in_dir(game.head, (1, 1));
+        assert_eq!(wall, 1.0 / 12.0);
+        assert!(is_food);
+
+        let (wall, _, _) = game.look_in_dir(game.head, (1, 0));
+        assert_eq!(wall, 1.0 / 4.0);
+    }
+
+    #[test]
+    fn score_only_counts_growth_since_the_start() {
+        let config = GameConfig {
+            start: StartConfig {
+                min_length: 4,
+                max_length: 4,
+                ..StartConfig::CLASSIC
+            },
+            ..GameConfig::DEFAULT
+        };
+        let mut game: Game = Game::with_seed(&config, 0);
+        assert_eq!(game.body.len(), 4);
+        assert_eq!(game.score(), 1);
+        assert_eq!(
+            game.fitness(),
+            Game::<Net>::with_seed(&GameConfig::DEFAULT, 0).fitness()
+        );
+
+        game.body.push(game.body[3]);
+        assert_eq!(game.score(), 2);
+        game.respawn(Point::new(3, 3));
+        assert_eq!(game.score(), 1);
+    }
+
+    /// Starts to the right on an empty board, `length` cells long
+    /// Food under the head is eaten by the next step
+    fn food_game(food: FoodConfig, length: usize) -> Game {
+        let config = GameConfig {
+            obstacles: Obstacles::None,
+            wrap: false,
+            food,
+            start: StartConfig {
+                min_length: length,
+                max_length: length,
+                dir: Some(FourDirs::Right),
+                ..StartConfig::CLASSIC
+            },
+            ..GameConfig::DEFAULT
+        };
+        Game::with_seed(&config, 0)
+    }
+
+    #[test]
+    fn poison_shrinks_and_ends_the_game_at_the_minimum_length() {
+        let food = FoodConfig {
+            num_poison: 1,
+            poison_shrink: 2,
+            ..FoodConfig::DEFAULT
+        };
+        let mut game = food_game(food, 4);
+        let normal = game.food[0];
+        game.food = vec![normal, Food::new(game.head, FoodKind::Poison)];
+        game.update_with_dir(game.dir);
+        assert_eq!(game.body.len(), 2);
+        assert!(!game.is_complete);
+        assert_eq!(game.num_food_eaten(), 0);
+        // Eaten poison shows up somewhere else
+        let poison: Vec<&Food> = game
+            .food
+            .iter()
+            .filter(|f| f.kind == FoodKind::Poison)
+            .collect();
+        assert_eq!(poison.len(), 1);
+        assert_ne!(poison[0].pos, game.head);
+
+        game.food = vec![normal, Food::new(game.head, FoodKind::Poison)];
+        game.update_with_dir(game.dir);
+        assert!(game.is_complete);
+        assert_eq!(game.termination(), Some(TerminationReason::Poison));
+        assert_eq!(GameCounts::from_games(&[game]).poison, 1);
+    }
+
+    #[test]
+    fn bonus_food_expires_and_grows_the_snake_more() {
+        let food = FoodConfig {
+            bonus_steps: 3,
+            bonus_growth: 3,
+            ..FoodConfig::DEFAULT
+        };
+        let mut game = food_game(food, 1);
+        game.add_food(FoodKind::Bonus);
+        assert_eq!(game.food[1].expires_in, Some(3));
+        for expires_in in [2, 1] {
+            game.update_bonus_food();
+            assert_eq!(game.food[1].expires_in, Some(expires_in));
+        }
+        game.update_bonus_food();
+        assert_eq!(game.food.len(), 1);
+        assert_eq!(game.food[0].kind, FoodKind::Normal);
+
+        game.add_food(FoodKind::Bonus);
+        game.food[1].pos = game.head;
+        game.food[0].pos = Point::new(1, 1);
+        game.update_with_dir(game.dir);
+        assert_eq!(game.body.len(), 1 + 3);
+        assert_eq!(game.num_food_eaten(), 1);
+        assert_eq!(game.score(), 4);
+        assert!(game.food.iter().all(|f| f.kind != FoodKind::Bonus));
+    }
+
+    #[test]
+    fn vision_has_the_nearest_food_of_each_kind() {
+        let classic = food_game(FoodConfig::DEFAULT, 1);
+        assert_eq!(classic.observe().len(), classic.config.num_inputs());
+        assert_eq!(classic.observe().len(), VisionMode::FourDir.num_inputs());
+
+        let food = FoodConfig {
+            num_poison: 1,
+            ..FoodConfig::DEFAULT
+        };
+        let mut game = food_game(food, 1);
+        let head = game.head;
+        game.food = vec![
+            Food::new(Point::new(head.x + 2, head.y), FoodKind::Normal),
+            Food::new(Point::new(head.x + 5, head.y + 5), FoodKind::Normal),
+            Food::new(Point::new(head.x, head.y - 3), FoodKind::Poison),
+        ];
+        let vision = game.observe();
+        assert_eq!(vision.len(), game.config.num_inputs());
+        assert_eq!(vision.len(), VisionMode::FourDir.num_inputs() + 6);
+
+        let (w, h) = (GRID_W as f64, GRID_H as f64);
+        // No bonus food on the board
+        let expected = [2.0 / w, 0.0, 0.0, 0.0, 0.0, -3.0 / h];
+        assert_eq!(vision[VisionMode::FourDir.num_inputs()..], expected);
+    }
+
+    #[test]
+    fn recurrent_memory_starts_zeroed_every_game() {
+        let config = GameConfig {
+            recurrent: true,
+            ..GameConfig::DEFAULT
+        };
+        let mut game: Game = Game::with_seed(&config, 0);
+        let vision = game.observe();
+        let zeroed = game.brain.predict(&vision).pop().unwrap();
+        // Same board every time, only the memory changes the output
+        let first = game
+            .brain
+            .layer_values(&vision, &game.memory)
+            .pop()
+            .unwrap();
+        game.next_dir();
+        let second = game
+            .brain
+            .layer_values(&vision, &game.memory)
+            .pop()
+            .unwrap();
+        assert_eq!(first, zeroed);
+        assert_ne!(second, first);
+
+        let mut next: Game = Game::with_seed(&config, 0);
+        next.brain = game.brain.clone();
+        assert_eq!(
+            next.brain
+                .layer_values(&vision, &next.memory)
+                .pop()
+                .unwrap(),
+            zeroed
+        );
+    }
+
+    /// Goes up and keeps every input it was given
+    #[derive(Clone)]
+    struct RecordingBrain {
+        seen: Arc<std::sync::Mutex<Vec<Vec<f64>>>>,
+    }
+
+    impl Brain for RecordingBrain {
+        type State = ();
+
+        fn random(_config: &GameConfig) -> Self {
+            Self {
+                seen: Arc::default(),
+            }
+        }
+
+        fn num_inputs(&self) -> usize {
+            VisionMode::FourDir.num_inputs()
+        }
+
+        fn predict(&self, inputs: &[f64], _state: &mut ()) -> Vec<f64> {
+            self.seen.lock().unwrap().push(inputs.to_vec());
+            vec![0.0, 0.0, 0.0, 1.0]
+        }
+
+        fn layer_values(&self, inputs: &[f64], _state: &()) -> Vec<Vec<f64>> {
+            vec![inputs.to_vec()]
+        }
+
+        fn crossover(&self, _other: &Self) -> Self {
+            self.clone()
+        }
+
+        fn mutate(&mut self, _rate: f32, _variation: f32) {}
+
+        fn distance(&self, _other: &Self) -> f64 {
+            0.0
+        }
+
+        fn is_compatible(&self, _other: &Self) -> bool {
+            true
+        }
+
+        fn to_text(&self) -> String {
+            String::new()
+        }
+
+        fn from_text(_text: &str) -> io::Result<Self> {
+            Ok(Self::random(&GameConfig::DEFAULT))
+        }
+    }
+
+    #[test]
+    fn observe_is_what_the_brain_sees() {
+        let mut game: Game<RecordingBrain> = Game::with_seed(&GameConfig::DEFAULT, 0);
+        for step in 0..3 {
+            let observed = game.observe();
+            game.update();
+            let seen = game.brain.seen.lock().unwrap();
+            assert_eq!(seen.len(), step + 1);
+            assert_eq!(seen[step], observed);
+        }
+        assert_eq!(game.dir, FourDirs::Top);
+    }
+
+    #[test]
+    fn state_matches_the_game() {
+        let mut game = food_game(FoodConfig::DEFAULT, 1);
+        let start = game.head;
+        game.food[0].pos = Point::new(start.x + 1, start.y);
+        for dir in [FourDirs::Right, FourDirs::Right, FourDirs::Top] {
+            game.update_with_dir(dir);
+        }
+
+        let state = game.state();
+        assert_eq!(state.body[0], game.head);
+        assert_eq!(state.body, game.body);
+        assert_eq!(
+            state.body,
+            vec![
+                Point::new(start.x + 2, start.y - 1),
+                Point::new(start.x + 2, start.y)
+            ]
+        );
+        assert_eq!(state.food, game.food);
+        assert_ne!(state.food[0].pos, Point::new(start.x + 1, start.y));
+        assert_eq!(state.dir, FourDirs::Top);
+        assert_eq!(state.score, 2);
+        assert_eq!(state.num_steps, 3);
+        assert_eq!(state.num_food_eaten, 1);
+        assert_eq!(state.termination, None);
+        assert!(!game.is_complete);
+
+        while !game.is_complete {
+            game.update_with_dir(FourDirs::Top);
+        }
+        let state = game.state();
+        assert_eq!(state.termination, Some(TerminationReason::Wall));
+        assert_eq!(state.body[0], game.head);
+        assert_eq!(state.body[0].y, 0);
+    }
+
+    #[test]
+    fn counts_add_up_across_streams_of_different_sizes() {
+        use TerminationReason::*;
+        let games = |reasons: &[Option<TerminationReason>]| -> Vec<Game> {
+            reasons
+                .iter()
+                .map(|&reason| {
+                    let mut game: Game = Game::with_seed(&GameConfig::DEFAULT, 0);
+                    if let Some(reason) = reason {
+                        game.complete(reason);
+                    }
+                    game
+                })
+                .collect()
+        };
+        let first = games(&[None, Some(Wall), Some(Body), None, Some(Wall)]);
+        let second = games(&[Some(Starvation), Some(Poison), None]);
+        let third = games(&[]);
+
+        let counts = GameCounts::from_games(&first);
+        assert_eq!(
+            counts,
+            GameCounts {
+                alive: 2,
+                completed: 3,
+                wall: 2,
+                body: 1,
+                starvation: 0,
+                poison: 0,
+            }
+        );
+
+        let mut total = GameCounts::default();
+        for stream in [&first, &second, &third] {
+            total += GameCounts::from_games(stream);
+        }
+        assert_eq!(
+            total,
+            GameCounts {
+                alive: 3,
+                completed: 5,
+                wall: 2,
+                body: 1,
+                starvation: 1,
+                poison: 1,
+            }
+        );
+        assert_eq!(total.total(), 8);
+    }
+}
@@ -0,0 +1,252 @@
+//! Hall of Fame
+//! Top brains ever seen across all generations and streams
+//! Ranked by their average score on the validation seeds instead of a single lucky game
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::brain::Brain;
+use crate::curriculum::CurriculumStage;
+use crate::eval::{self, EvalResult};
+use crate::game::{Game, GameConfig};
+use crate::nn::Net;
+use crate::*;
+
+#[derive(Clone)]
+pub struct HallOfFameEntry<B: Brain = Net> {
+    pub net: B,
+    pub game_config: GameConfig,
+    pub score: usize,
+    pub generation: usize,
+    pub stream: usize,
+    pub eval: EvalResult,
+}
+
+pub struct HallOfFame<B: Brain = Net> {
+    entries: Vec<HallOfFameEntry<B>>,
+}
+
+impl<B: Brain> HallOfFame<B> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Entries sorted by their average score, best first
+    pub fn entries(&self) -> &[HallOfFameEntry<B>] {
+        &self.entries
+    }
+
+    pub fn best(&self) -> Option<&HallOfFameEntry<B>> {
+        self.entries.first()
+    }
+
+    /// Keeps the validated brain if it makes the top `HALL_OF_FAME_SIZE`
+    pub fn consider(&mut self, game: &Game<B>, eval: EvalResult, generation: usize, stream: usize) {
+        let is_known = self
+            .entries
+            .iter()
+            .any(|e| e.net.is_compatible(&game.brain) && e.net.distance(&game.brain) == 0.0);
+        if is_known {
+            return;
+        }
+
+        let is_full = self.entries.len() >= HALL_OF_FAME_SIZE;
+        if is_full
+            && self
+                .entries
+                .last()
+                .is_some_and(|e| e.eval.mean >= eval.mean)
+        {
+            return;
+        }
+
+        self.entries.push(HallOfFameEntry {
+            net: game.brain.clone(),
+            game_config: game.config.clone(),
+            score: game.score(),
+            generation,
+            stream,
+            eval,
+        });
+        self.sort();
+        self.entries.truncate(HALL_OF_FAME_SIZE);
+    }
+
+    /// Moves every entry to the stage's board and validates it again
+    /// Scores from an easier stage would otherwise keep newer brains out
+    pub fn apply_curriculum_stage(&mut self, stage: &CurriculumStage) {
+        for entry in self.entries.iter_mut() {
+            stage.apply(&mut entry.game_config);
+            entry.eval = eval::validate(&entry.net, &entry.game_config);
+        }
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by(|a, b| {
+            b.eval
+                .mean
+                .partial_cmp(&a.eval.mean)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    /// Writes every brain to its own file along with an index.csv of the stats
+    /// Brains left from an earlier save are removed, they would be loaded along with the new ones
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if is_brain_file(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        let mut index = vec!["rank,file,score,avg_score,std_score,generation,stream".to_string()];
+        for (rank, entry) in self.entries.iter().enumerate() {
+            let file_name = format!("brain_{}.txt", rank);
+            fs::write(dir.join(&file_name), entry.net.to_text())?;
+            index.push(format!(
+                "{},{},{},{:.2},{:.2},{},{}",
+                rank,
+                file_name,
+                entry.score,
+                entry.eval.mean,
+                entry.eval.std,
+                entry.generation,
+                entry.stream
+            ));
+        }
+
+        fs::write(dir.join("index.csv"), index.join("\n") + "\n")
+    }
+}
+
+/// Named like the files of `HallOfFame::save`, eg. brain_3.txt
+fn is_brain_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix("brain_"))
+        .and_then(|name| name.strip_suffix(".txt"))
+        .is_some_and(|rank| !rank.is_empty() && rank.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Every brain saved as a .txt file in `dir`, in the order of their file names
+/// Files that can't be read or aren't a `B`, eg. a NEAT genome for a `Net`, are skipped with a warning
+pub fn load_brains<B: Brain>(dir: &Path) -> io::Result<Vec<B>> {
+    Ok(load_brain_files(dir)?
+        .into_iter()
+        .map(|(_, brain)| brain)
+        .collect())
+}
+
+/// Same as `load_brains` along with the path of every brain
+pub fn load_brain_files<B: Brain>(dir: &Path) -> io::Result<Vec<(PathBuf, B)>> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    paths.retain(|p| p.extension().is_some_and(|ext| ext == "txt"));
+    // Shorter names first so brain_2 comes before brain_10
+    paths.sort_by_key(|p| (p.as_os_str().len(), p.clone()));
+
+    let mut brains = Vec::new();
+    for path in paths {
+        match fs::read_to_string(&path).and_then(|text| B::from_text(&text)) {
+            Ok(brain) => brains.push((path, brain)),
+            Err(e) => println!("Skipping the brain {}: {}", path.display(), e),
+        }
+    }
+
+    Ok(brains)
+}
+
+impl<B: Brain> Default for HallOfFame<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(net: &Net) -> Game {
+        let mut game: Game = Game::with_config(&GameConfig::DEFAULT);
+        game.brain = net.clone();
+        game
+    }
+
+    fn eval(mean: f32) -> EvalResult {
+        EvalResult { mean, std: 0.0 }
+    }
+
+    fn means(hall_of_fame: &HallOfFame) -> Vec<f32> {
+        hall_of_fame.entries().iter().map(|e| e.eval.mean).collect()
+    }
+
+    #[test]
+    fn consider_keeps_the_best_distinct_brains() {
+        let mut hall_of_fame = HallOfFame::new();
+        let net = Net::random(&GameConfig::DEFAULT);
+        hall_of_fame.consider(&game(&net), eval(3.0), 0, 0);
+        // The same brain again, even with a better mean
+        hall_of_fame.consider(&game(&net), eval(9.0), 1, 0);
+        assert_eq!(means(&hall_of_fame), vec![3.0]);
+
+        for idx in 0..HALL_OF_FAME_SIZE + 5 {
+            let net = Net::random(&GameConfig::DEFAULT);
+            hall_of_fame.consider(&game(&net), eval(idx as f32 + 0.5), idx, 1);
+        }
+        let kept = means(&hall_of_fame);
+        assert_eq!(kept.len(), HALL_OF_FAME_SIZE);
+        assert!(kept.windows(2).all(|w| w[0] >= w[1]));
+        assert_eq!(kept[0], (HALL_OF_FAME_SIZE + 4) as f32 + 0.5);
+        assert_eq!(
+            hall_of_fame.best().unwrap().generation,
+            HALL_OF_FAME_SIZE + 4
+        );
+
+        // Not better than the last entry of a full hall of fame
+        let worst = *kept.last().unwrap();
+        hall_of_fame.consider(
+            &game(&Net::random(&GameConfig::DEFAULT)),
+            eval(worst),
+            99,
+            2,
+        );
+        assert_eq!(means(&hall_of_fame), kept);
+    }
+
+    #[test]
+    fn save_replaces_the_brains_of_an_earlier_save() {
+        let dir = std::env::temp_dir().join("snake_hall_of_fame_save");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("brain_12.txt"), Net::new().to_text()).unwrap();
+        fs::write(dir.join("notes.txt"), "kept").unwrap();
+
+        let mut hall_of_fame = HallOfFame::new();
+        let nets: Vec<Net> = (0..3).map(|_| Net::random(&GameConfig::DEFAULT)).collect();
+        for (idx, net) in nets.iter().enumerate() {
+            hall_of_fame.consider(&game(net), eval(idx as f32), idx, 0);
+        }
+        hall_of_fame.save(&dir).unwrap();
+
+        let loaded: Vec<(PathBuf, Net)> = load_brain_files(&dir).unwrap();
+        let names: Vec<String> = loaded
+            .iter()
+            .map(|(p, _)| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["brain_0.txt", "brain_1.txt", "brain_2.txt"]);
+        // Best first
+        for ((_, loaded), net) in loaded.iter().zip(nets.iter().rev()) {
+            assert_eq!(loaded.params(), net.params());
+        }
+        assert!(dir.join("notes.txt").exists());
+        let index = fs::read_to_string(dir.join("index.csv")).unwrap();
+        assert_eq!(index.lines().count(), 4);
+    }
+}
@@ -0,0 +1,201 @@
+//! Levels
+//! Obstacles inside the board, from text level files or random generators
+//! Snakes see obstacles the same way as the outer walls
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use rand::Rng;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Obstacles {
+    None,
+    /// Random cells, the value is the share of the cells inside the walls
+    Scattered(f32),
+    /// Random horizontal and vertical wall segments
+    Segments {
+        count: usize,
+        max_len: i32,
+    },
+    /// Level file, its size replaces the grid size of the game config
+    File(&'static str),
+}
+
+/// Board loaded from a level file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub grid_w: i32,
+    pub grid_h: i32,
+    /// One flag per cell row by row, including the border
+    cells: Vec<bool>,
+}
+
+impl Obstacles {
+    /// Obstacle flags indexed by `cell_index`, kept off the `free` cells
+    /// and out of the area around every one of the `spawns`
+    /// Level files are ignored, see `Level`
+    pub fn generate(
+        &self,
+        grid_w: i32,
+        grid_h: i32,
+        rng: &mut impl Rng,
+        free: &[Point],
+        spawns: &[Point],
+    ) -> Vec<bool> {
+        let mut cells = vec![false; ((grid_w + 1) * (grid_h + 1)) as usize];
+        let is_allowed = |pt: Point| {
+            let is_inside = pt.x > 0 && pt.x < grid_w && pt.y > 0 && pt.y < grid_h;
+            let is_near_spawn = spawns
+                .iter()
+                .any(|s| (pt.x - s.x).abs() <= 2 && (pt.y - s.y).abs() <= 2);
+            is_inside && !is_near_spawn && !free.contains(&pt)
+        };
+
+        match *self {
+            Self::None | Self::File(_) => {}
+            Self::Scattered(density) => {
+                if density <= 0.0 {
+                    return cells;
+                }
+                for y in 1..grid_h {
+                    for x in 1..grid_w {
+                        let pt = Point::new(x, y);
+                        if is_allowed(pt) && rng.gen::<f32>() < density {
+                            cells[cell_index(grid_w, pt)] = true;
+                        }
+                    }
+                }
+            }
+            Self::Segments { count, max_len } => {
+                for _ in 0..count {
+                    let mut pt = Point::new(rng.gen_range(1..grid_w), rng.gen_range(1..grid_h));
+                    let dir = FourDirs::get_rand_dir(rng);
+                    for _ in 0..rng.gen_range(1..=max_len.max(1)) {
+                        if is_allowed(pt) {
+                            cells[cell_index(grid_w, pt)] = true;
+                        }
+                        pt = Point::new(pt.x + dir.value().0, pt.y + dir.value().1);
+                    }
+                }
+            }
+        }
+
+        cells
+    }
+
+    /// The parsed level file, None for the random obstacles
+    /// Level files are read once and shared by every config using them
+    pub fn load_level(&self) -> io::Result<Option<Arc<Level>>> {
+        let Self::File(path) = *self else {
+            return Ok(None);
+        };
+
+        cached_level(path).map(Some)
+    }
+}
+
+impl Level {
+    /// One line per row, including the border walls, at least 4 rows and columns
+    /// `#` is a wall and `.` an empty cell, the center cell where snakes spawn must be empty
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let bad_data = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let rows: Vec<&str> = text
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        let width = rows.first().map(|r| r.chars().count()).unwrap_or(0);
+        if rows.len() < 4 || width < 4 {
+            return Err(bad_data("Level is too small"));
+        }
+        if rows.iter().any(|r| r.chars().count() != width) {
+            return Err(bad_data("Level rows have different lengths"));
+        }
+
+        let mut cells = Vec::with_capacity(rows.len() * width);
+        for row in rows.iter() {
+            for c in row.chars() {
+                match c {
+                    '#' => cells.push(true),
+                    '.' => cells.push(false),
+                    _ => return Err(bad_data("Unknown level cell")),
+                }
+            }
+        }
+
+        let level = Self {
+            grid_w: width as i32 - 1,
+            grid_h: rows.len() as i32 - 1,
+            cells,
+        };
+        let spawn = Point::new(level.grid_w / 2, level.grid_h / 2);
+        if level.cells[cell_index(level.grid_w, spawn)] {
+            return Err(bad_data("Level spawn cell is a wall"));
+        }
+
+        Ok(level)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Obstacle flags indexed by `cell_index`, the border is left to the game
+    pub fn cells(&self) -> &[bool] {
+        &self.cells
+    }
+}
+
+/// Index of the cell in the obstacle flags of a board
+pub fn cell_index(grid_w: i32, pt: Point) -> usize {
+    (pt.y * (grid_w + 1) + pt.x) as usize
+}
+
+/// Level files are parsed once and shared by every game
+fn cached_level(path: &str) -> io::Result<Arc<Level>> {
+    static LEVELS: OnceLock<Mutex<HashMap<String, Arc<Level>>>> = OnceLock::new();
+    let mut levels = LEVELS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    if let Some(level) = levels.get(path) {
+        return Ok(level.clone());
+    }
+
+    let level = Arc::new(
+        Level::load(Path::new(path))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?,
+    );
+    levels.insert(path.to_string(), level.clone());
+    Ok(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_text_reads_walls_and_size() {
+        let level = Level::from_text("#####\n#..##\n#...#\n#####\n").unwrap();
+        assert_eq!((level.grid_w, level.grid_h), (4, 3));
+        assert!(level.cells()[cell_index(4, Point::new(3, 1))]);
+        assert!(!level.cells()[cell_index(4, Point::new(1, 1))]);
+        assert!(level.cells()[cell_index(4, Point::new(0, 2))]);
+    }
+
+    #[test]
+    fn from_text_rejects_bad_levels() {
+        // Too small to draw random cells inside the walls
+        assert!(Level::from_text("###\n#.#\n###\n").is_err());
+        assert!(Level::from_text("####\n#..#\n####\n").is_err());
+        assert!(Level::from_text("####\n#..#\n#.#\n####\n").is_err());
+        assert!(Level::from_text("####\n#.x#\n#..#\n####\n").is_err());
+        // Wall on the spawn cell
+        assert!(Level::from_text("#####\n#...#\n#.#.#\n#...#\n#####\n").is_err());
+    }
+}
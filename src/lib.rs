@@ -1,9 +1,25 @@
+pub mod arena;
+pub mod brain;
 pub mod configs;
+pub mod curriculum;
+pub mod es;
+pub mod eval;
+pub mod food;
 pub mod game;
+pub mod hall_of_fame;
+pub mod level;
+pub mod migration;
+pub mod neat;
 pub mod nn;
+pub mod novelty;
+pub mod pareto;
 pub mod pop;
 pub mod sim;
+pub mod species;
+pub mod start;
 pub mod stream;
+pub mod tournament;
+pub mod train;
 pub mod utils;
 pub mod viz;
 
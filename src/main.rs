@@ -18,7 +18,13 @@ fn window_conf() -> Conf {
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    let mut sim = Simulation::new();
+    let mut sim = match Simulation::<SimBrain>::new() {
+        Ok(sim) => sim,
+        Err(e) => {
+            println!("Failed to start the simulation: {}", e);
+            return;
+        }
+    };
     let mut is_viz_enabled = true;
     let mut is_slow_mode = true;
 
@@ -49,6 +55,22 @@ async fn main() {
         if is_key_released(KeyCode::Space) {
             is_slow_mode = !is_slow_mode;
         }
+        if is_key_pressed(KeyCode::S) {
+            sim.save_hall_of_fame();
+        }
+        if is_key_pressed(KeyCode::P) {
+            sim.toggle_human_play();
+        }
+        for (key, dir) in [
+            (KeyCode::Left, FourDirs::Left),
+            (KeyCode::Right, FourDirs::Right),
+            (KeyCode::Down, FourDirs::Bottom),
+            (KeyCode::Up, FourDirs::Top),
+        ] {
+            if is_key_pressed(key) {
+                sim.steer(dir);
+            }
+        }
 
         if is_slow_mode {
             thread::sleep(Duration::from_millis(SIM_SLEEP_MILLIS));
@@ -0,0 +1,103 @@
+//! Migration
+//! Decides which streams (islands) exchange snakes
+//! and which snakes are replaced by the migrants
+
+use rand::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MigrationTopology {
+    /// Every stream receives from the previous one
+    Ring,
+    /// Every stream receives from all the others
+    FullyConnected,
+    /// Stream 0 is the hub, it receives from all the others and sends to all of them
+    Star,
+    /// Every stream receives from another random stream
+    Random,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplacementPolicy {
+    /// Migrants replace the least fit snakes
+    Worst,
+    /// Migrants replace random snakes
+    Random,
+}
+
+impl MigrationTopology {
+    /// Streams that send migrants to `target`
+    pub fn sources(&self, target: usize, num_streams: usize) -> Vec<usize> {
+        if num_streams <= 1 {
+            return Vec::new();
+        }
+
+        match self {
+            Self::Ring => vec![(target + num_streams - 1) % num_streams],
+            Self::FullyConnected => (0..num_streams).filter(|&i| i != target).collect(),
+            Self::Star => {
+                if target == 0 {
+                    (1..num_streams).collect()
+                } else {
+                    vec![0]
+                }
+            }
+            Self::Random => {
+                let mut rng = rand::thread_rng();
+                let source = rng.gen_range(0..num_streams - 1);
+                if source >= target {
+                    vec![source + 1]
+                } else {
+                    vec![source]
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_receives_from_the_previous_stream() {
+        assert_eq!(MigrationTopology::Ring.sources(0, 4), vec![3]);
+        assert_eq!(MigrationTopology::Ring.sources(2, 4), vec![1]);
+    }
+
+    #[test]
+    fn fully_connected_receives_from_all_the_others() {
+        assert_eq!(
+            MigrationTopology::FullyConnected.sources(1, 4),
+            vec![0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn star_goes_through_the_hub() {
+        assert_eq!(MigrationTopology::Star.sources(0, 4), vec![1, 2, 3]);
+        assert_eq!(MigrationTopology::Star.sources(3, 4), vec![0]);
+    }
+
+    #[test]
+    fn random_never_picks_the_target() {
+        for target in 0..3 {
+            for _ in 0..100 {
+                let sources = MigrationTopology::Random.sources(target, 3);
+                assert_eq!(sources.len(), 1);
+                assert!(sources[0] < 3 && sources[0] != target);
+            }
+        }
+    }
+
+    #[test]
+    fn single_stream_has_no_sources() {
+        for topology in [
+            MigrationTopology::Ring,
+            MigrationTopology::FullyConnected,
+            MigrationTopology::Star,
+            MigrationTopology::Random,
+        ] {
+            assert!(topology.sources(0, 1).is_empty());
+        }
+    }
+}
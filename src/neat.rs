@@ -0,0 +1,739 @@
+//! NEAT
+//! Genome whose topology evolves along with its weights
+//! Node and connection genes with innovation numbers, feed-forward only
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Mutex, OnceLock};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::brain::Brain;
+use crate::game::GameConfig;
+use crate::*;
+
+/// Inputs, bias and outputs use the ids below this one
+const FIRST_HIDDEN_ID: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeKind {
+    Input,
+    /// Always outputs 1
+    Bias,
+    Hidden,
+    Output,
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeGene {
+    pub id: usize,
+    pub kind: NodeKind,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConnectionGene {
+    pub innovation: usize,
+    pub from: usize,
+    pub to: usize,
+    pub weight: f64,
+    pub is_enabled: bool,
+}
+
+/// Nodes are ordered inputs, bias, outputs and then hidden nodes
+#[derive(Clone)]
+pub struct Genome {
+    n_inputs: usize,
+    n_outputs: usize,
+    nodes: Vec<NodeGene>,
+    /// Sorted by innovation number
+    connections: Vec<ConnectionGene>,
+    /// Hidden and output node indices in evaluation order,
+    /// with the node index and weight of their enabled incoming connections
+    plan: Vec<(usize, Vec<(usize, f64)>)>,
+}
+
+/// The same structural mutation gets the same innovation number for the whole run
+struct Innovations {
+    connections: HashMap<(usize, usize), usize>,
+    /// Hidden node created by splitting a connection, keyed by the connection's innovation
+    splits: HashMap<usize, usize>,
+    /// Hidden node ids of loaded genomes mapped to ids of this run
+    loaded_nodes: HashMap<usize, usize>,
+    next_node_id: usize,
+}
+
+impl Innovations {
+    fn connection(&mut self, from: usize, to: usize) -> usize {
+        let next = self.connections.len();
+        *self.connections.entry((from, to)).or_insert(next)
+    }
+
+    fn split(&mut self, innovation: usize) -> usize {
+        let next = self.next_node_id;
+        let id = *self.splits.entry(innovation).or_insert(next);
+        if id == next {
+            self.next_node_id += 1;
+        }
+
+        id
+    }
+
+    /// The same saved id gives the same node, never one of the split nodes of this run
+    fn loaded_node(&mut self, saved_id: usize) -> usize {
+        let next = self.next_node_id;
+        let id = *self.loaded_nodes.entry(saved_id).or_insert(next);
+        if id == next {
+            self.next_node_id += 1;
+        }
+
+        id
+    }
+}
+
+fn innovations() -> &'static Mutex<Innovations> {
+    static INNOVATIONS: OnceLock<Mutex<Innovations>> = OnceLock::new();
+    INNOVATIONS.get_or_init(|| {
+        Mutex::new(Innovations {
+            connections: HashMap::new(),
+            splits: HashMap::new(),
+            loaded_nodes: HashMap::new(),
+            next_node_id: FIRST_HIDDEN_ID,
+        })
+    })
+}
+
+impl Genome {
+    /// No hidden nodes, every input and the bias connected to every output
+    pub fn new(n_inputs: usize, n_outputs: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        let nodes = Self::base_nodes(n_inputs, n_outputs);
+        let mut connections = Vec::new();
+        let mut innovations = innovations().lock().unwrap();
+        for from in 0..=n_inputs {
+            for to in n_inputs + 1..n_inputs + 1 + n_outputs {
+                connections.push(ConnectionGene {
+                    innovation: innovations.connection(from, to),
+                    from,
+                    to,
+                    weight: rng.gen_range(-1.0f64..1.0f64),
+                    is_enabled: true,
+                });
+            }
+        }
+        drop(innovations);
+
+        Self::from_genes(n_inputs, n_outputs, nodes, connections)
+    }
+
+    /// Inputs, bias and outputs
+    fn base_nodes(n_inputs: usize, n_outputs: usize) -> Vec<NodeGene> {
+        let mut nodes = Vec::new();
+        for id in 0..n_inputs {
+            nodes.push(NodeGene {
+                id,
+                kind: NodeKind::Input,
+            });
+        }
+        nodes.push(NodeGene {
+            id: n_inputs,
+            kind: NodeKind::Bias,
+        });
+        for id in n_inputs + 1..n_inputs + 1 + n_outputs {
+            nodes.push(NodeGene {
+                id,
+                kind: NodeKind::Output,
+            });
+        }
+
+        nodes
+    }
+
+    fn from_genes(
+        n_inputs: usize,
+        n_outputs: usize,
+        nodes: Vec<NodeGene>,
+        mut connections: Vec<ConnectionGene>,
+    ) -> Self {
+        connections.sort_by_key(|c| c.innovation);
+        let mut genome = Self {
+            n_inputs,
+            n_outputs,
+            nodes,
+            connections,
+            plan: Vec::new(),
+        };
+        genome.build_plan();
+
+        genome
+    }
+
+    pub fn nodes(&self) -> &[NodeGene] {
+        &self.nodes
+    }
+
+    pub fn connections(&self) -> &[ConnectionGene] {
+        &self.connections
+    }
+
+    pub fn num_hidden(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| n.kind == NodeKind::Hidden)
+            .count()
+    }
+
+    pub fn predict(&self, inputs: &[f64]) -> Vec<f64> {
+        let values = self.node_values(inputs);
+        let first_output = self.n_inputs + 1;
+        values[first_output..first_output + self.n_outputs].to_vec()
+    }
+
+    /// Inputs, hidden nodes grouped by their depth and outputs
+    /// The bias isn't included
+    pub fn layer_values(&self, inputs: &[f64]) -> Vec<Vec<f64>> {
+        let values = self.node_values(inputs);
+        let mut depths = vec![0; self.nodes.len()];
+        for (idx, incoming) in self.plan.iter() {
+            depths[*idx] = incoming
+                .iter()
+                .map(|(from, _)| depths[*from] + 1)
+                .max()
+                .unwrap_or(1);
+        }
+
+        let max_depth = self
+            .nodes
+            .iter()
+            .zip(depths.iter())
+            .filter(|(n, _)| n.kind == NodeKind::Hidden)
+            .map(|(_, &d)| d)
+            .max()
+            .unwrap_or(0);
+        let mut hidden = vec![Vec::new(); max_depth];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.kind == NodeKind::Hidden {
+                hidden[depths[idx] - 1].push(values[idx]);
+            }
+        }
+
+        let first_output = self.n_inputs + 1;
+        let mut layers = vec![values[..self.n_inputs].to_vec()];
+        layers.extend(hidden.into_iter().filter(|l| !l.is_empty()));
+        layers.push(values[first_output..first_output + self.n_outputs].to_vec());
+
+        layers
+    }
+
+    /// Value of every node, in the order of `nodes`
+    fn node_values(&self, inputs: &[f64]) -> Vec<f64> {
+        if inputs.len() != self.n_inputs {
+            panic!(
+                "Bad input size, expected {:?} but got {:?}",
+                self.n_inputs,
+                inputs.len()
+            );
+        }
+
+        let mut values = vec![0.0; self.nodes.len()];
+        values[..self.n_inputs].copy_from_slice(inputs);
+        values[self.n_inputs] = 1.0;
+        for (idx, incoming) in self.plan.iter() {
+            let total: f64 = incoming.iter().map(|(from, w)| values[*from] * w).sum();
+            values[*idx] = 1f64 / (1f64 + (-total).exp());
+        }
+
+        values
+    }
+
+    /// Perturbs the weights like `Net`, then maybe adds a connection and a node
+    pub fn mutate(&mut self, rate: f32, variation: f32) {
+        let mut rng = rand::thread_rng();
+        for connection in self.connections.iter_mut() {
+            if rng.gen_range(0.0..1.0) >= rate {
+                continue;
+            }
+
+            connection.weight += rng.gen_range(-variation..variation) as f64;
+            if connection.weight > 1.0 || connection.weight < -1.0 {
+                connection.weight = rng.gen_range(-1.0f64..1.0f64);
+            }
+        }
+
+        if rng.gen_range(0.0..1.0) < NEAT_ADD_CONNECTION_RATE {
+            self.add_connection();
+        }
+        if rng.gen_range(0.0..1.0) < NEAT_ADD_NODE_RATE {
+            self.add_node();
+        }
+        self.build_plan();
+    }
+
+    /// `self` is the fitter parent, its disjoint and excess genes are kept
+    /// Matching genes take their weight from either parent
+    pub fn crossover(&self, other: &Genome) -> Self {
+        let mut rng = rand::thread_rng();
+        let other_genes: HashMap<usize, &ConnectionGene> = other
+            .connections
+            .iter()
+            .map(|c| (c.innovation, c))
+            .collect();
+
+        let mut connections = Vec::new();
+        for gene in self.connections.iter() {
+            let mut child_gene = gene.clone();
+            if let Some(other_gene) = other_genes.get(&gene.innovation) {
+                if rng.gen::<bool>() {
+                    child_gene.weight = other_gene.weight;
+                }
+                // Disabled in either parent, likely disabled in the child
+                if !gene.is_enabled || !other_gene.is_enabled {
+                    child_gene.is_enabled = rng.gen_range(0.0..1.0) >= 0.75;
+                }
+            }
+            connections.push(child_gene);
+        }
+
+        Self::from_genes(
+            self.n_inputs,
+            self.n_outputs,
+            self.nodes.clone(),
+            connections,
+        )
+    }
+
+    /// Compatibility distance, the excess and disjoint genes
+    /// plus the mean weight difference of the matching genes
+    pub fn distance(&self, other: &Genome) -> f64 {
+        let (a, b) = (&self.connections, &other.connections);
+        let max_a = a.last().map(|c| c.innovation).unwrap_or(0);
+        let max_b = b.last().map(|c| c.innovation).unwrap_or(0);
+
+        let (mut i, mut j) = (0, 0);
+        let (mut excess, mut disjoint, mut num_matching) = (0, 0, 0);
+        let mut weight_diff = 0.0;
+        while i < a.len() || j < b.len() {
+            let innov_a = a.get(i).map(|c| c.innovation);
+            let innov_b = b.get(j).map(|c| c.innovation);
+            match (innov_a, innov_b) {
+                (Some(x), Some(y)) if x == y => {
+                    weight_diff += (a[i].weight - b[j].weight).abs();
+                    num_matching += 1;
+                    i += 1;
+                    j += 1;
+                }
+                (Some(x), y) if y.map_or(true, |y| x < y) => {
+                    if x > max_b {
+                        excess += 1;
+                    } else {
+                        disjoint += 1;
+                    }
+                    i += 1;
+                }
+                (_, Some(y)) => {
+                    if y > max_a {
+                        excess += 1;
+                    } else {
+                        disjoint += 1;
+                    }
+                    j += 1;
+                }
+                _ => break,
+            }
+        }
+
+        let num_genes = a.len().max(b.len()).max(1) as f64;
+        let mean_weight_diff = if num_matching > 0 {
+            weight_diff / num_matching as f64
+        } else {
+            0.0
+        };
+
+        NEAT_EXCESS_COEFF * excess as f64 / num_genes
+            + NEAT_DISJOINT_COEFF * disjoint as f64 / num_genes
+            + NEAT_WEIGHT_COEFF * mean_weight_diff
+    }
+
+    /// Plain text format, a `neat` header with the number of inputs and outputs
+    /// followed by one line per node and one line per connection
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("neat {} {}", self.n_inputs, self.n_outputs)];
+        for node in self.nodes.iter().filter(|n| n.kind == NodeKind::Hidden) {
+            lines.push(format!("node {}", node.id));
+        }
+        for c in self.connections.iter() {
+            lines.push(format!(
+                "conn {} {} {} {}",
+                c.from, c.to, c.weight, c.is_enabled as u8
+            ));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Innovation numbers are looked up again, so genomes from other runs stay comparable
+    /// Hidden node ids are mapped to ids of this run, see `Innovations::loaded_node`
+    /// Unknown nodes, connections into an input or out of an output and cycles are errors
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let bad_data = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| bad_data("Missing header"))?
+            .split_whitespace()
+            .collect();
+        let (n_inputs, n_outputs) = match header.as_slice() {
+            ["neat", i, o] => (
+                i.parse::<usize>().map_err(|_| bad_data("Bad header"))?,
+                o.parse::<usize>().map_err(|_| bad_data("Bad header"))?,
+            ),
+            _ => return Err(bad_data("Not a neat genome")),
+        };
+        if n_inputs == 0 || n_outputs == 0 || n_inputs + 1 + n_outputs > FIRST_HIDDEN_ID {
+            return Err(bad_data("Bad number of inputs or outputs"));
+        }
+
+        let mut nodes = Self::base_nodes(n_inputs, n_outputs);
+        // Saved id to the id of this run
+        let mut ids: HashMap<usize, usize> = nodes.iter().map(|n| (n.id, n.id)).collect();
+        let mut connections: Vec<ConnectionGene> = Vec::new();
+        let mut innovations = innovations().lock().unwrap();
+        for line in lines {
+            let values: Vec<&str> = line.split_whitespace().collect();
+            match values.as_slice() {
+                ["node", id] => {
+                    let saved_id = id.parse::<usize>().map_err(|_| bad_data("Bad node"))?;
+                    if saved_id < FIRST_HIDDEN_ID || ids.contains_key(&saved_id) {
+                        return Err(bad_data("Bad hidden node id"));
+                    }
+                    let id = innovations.loaded_node(saved_id);
+                    ids.insert(saved_id, id);
+                    nodes.push(NodeGene {
+                        id,
+                        kind: NodeKind::Hidden,
+                    });
+                }
+                ["conn", from, to, weight, enabled] => {
+                    let parse_id = |v: &str| -> io::Result<usize> {
+                        let saved_id = v.parse::<usize>().map_err(|_| bad_data("Bad conn"))?;
+                        ids.get(&saved_id)
+                            .copied()
+                            .ok_or_else(|| bad_data("Connection to an unknown node"))
+                    };
+                    let (from, to) = (parse_id(from)?, parse_id(to)?);
+                    let kind = |id: usize| nodes.iter().find(|n| n.id == id).unwrap().kind;
+                    if kind(from) == NodeKind::Output
+                        || matches!(kind(to), NodeKind::Input | NodeKind::Bias)
+                    {
+                        return Err(bad_data("Bad connection direction"));
+                    }
+                    if connections.iter().any(|c| c.from == from && c.to == to) {
+                        return Err(bad_data("Duplicate connection"));
+                    }
+                    // Disabled connections count too, a crossover can enable them again
+                    if Self::is_reachable(&connections, to, from) {
+                        return Err(bad_data("Connections form a cycle"));
+                    }
+                    connections.push(ConnectionGene {
+                        innovation: innovations.connection(from, to),
+                        from,
+                        to,
+                        weight: weight.parse().map_err(|_| bad_data("Bad weight"))?,
+                        is_enabled: *enabled == "1",
+                    });
+                }
+                _ => return Err(bad_data("Unknown line")),
+            }
+        }
+        drop(innovations);
+
+        Ok(Self::from_genes(n_inputs, n_outputs, nodes, connections))
+    }
+
+    fn add_connection(&mut self) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..NEAT_ADD_CONNECTION_TRIES {
+            let from = self.nodes.choose(&mut rng).unwrap();
+            let to = self.nodes.choose(&mut rng).unwrap();
+            if from.kind == NodeKind::Output
+                || matches!(to.kind, NodeKind::Input | NodeKind::Bias)
+                || from.id == to.id
+            {
+                continue;
+            }
+
+            let (from, to) = (from.id, to.id);
+            let is_known = self
+                .connections
+                .iter()
+                .any(|c| c.from == from && c.to == to);
+            // A path back from `to` would close a cycle
+            if is_known || Self::is_reachable(&self.connections, to, from) {
+                continue;
+            }
+
+            let innovation = innovations().lock().unwrap().connection(from, to);
+            self.connections.push(ConnectionGene {
+                innovation,
+                from,
+                to,
+                weight: rng.gen_range(-1.0f64..1.0f64),
+                is_enabled: true,
+            });
+            self.connections.sort_by_key(|c| c.innovation);
+            return;
+        }
+    }
+
+    /// Splits an enabled connection in two with a new hidden node in the middle
+    fn add_node(&mut self) {
+        let mut rng = rand::thread_rng();
+        let enabled: Vec<usize> = (0..self.connections.len())
+            .filter(|&idx| self.connections[idx].is_enabled)
+            .collect();
+        let Some(&idx) = enabled.choose(&mut rng) else {
+            return;
+        };
+
+        let split = self.connections[idx].clone();
+        let mut innovations = innovations().lock().unwrap();
+        let id = innovations.split(split.innovation);
+        // Already split once, the connection was re-enabled by a crossover
+        if self.nodes.iter().any(|n| n.id == id) {
+            return;
+        }
+
+        self.connections[idx].is_enabled = false;
+        self.nodes.push(NodeGene {
+            id,
+            kind: NodeKind::Hidden,
+        });
+        self.connections.push(ConnectionGene {
+            innovation: innovations.connection(split.from, id),
+            from: split.from,
+            to: id,
+            weight: 1.0,
+            is_enabled: true,
+        });
+        self.connections.push(ConnectionGene {
+            innovation: innovations.connection(id, split.to),
+            from: id,
+            to: split.to,
+            weight: split.weight,
+            is_enabled: true,
+        });
+        self.connections.sort_by_key(|c| c.innovation);
+    }
+
+    /// Follows every connection, disabled ones can come back with a crossover
+    fn is_reachable(connections: &[ConnectionGene], start: usize, target: usize) -> bool {
+        let mut stack = vec![start];
+        let mut visited = vec![start];
+        while let Some(id) = stack.pop() {
+            if id == target {
+                return true;
+            }
+            for c in connections.iter().filter(|c| c.from == id) {
+                if !visited.contains(&c.to) {
+                    visited.push(c.to);
+                    stack.push(c.to);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Topological order of the enabled connections
+    fn build_plan(&mut self) {
+        let index: HashMap<usize, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, n)| (n.id, idx))
+            .collect();
+
+        let mut incoming: Vec<Vec<(usize, f64)>> = vec![Vec::new(); self.nodes.len()];
+        let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); self.nodes.len()];
+        let mut num_deps = vec![0; self.nodes.len()];
+        for c in self.connections.iter().filter(|c| c.is_enabled) {
+            let (from, to) = (index[&c.from], index[&c.to]);
+            incoming[to].push((from, c.weight));
+            outgoing[from].push(to);
+            num_deps[to] += 1;
+        }
+
+        let mut ready: Vec<usize> = (0..self.nodes.len())
+            .filter(|&idx| num_deps[idx] == 0)
+            .collect();
+        self.plan.clear();
+        while let Some(idx) = ready.pop() {
+            for &to in outgoing[idx].iter() {
+                num_deps[to] -= 1;
+                if num_deps[to] == 0 {
+                    ready.push(to);
+                }
+            }
+            if matches!(self.nodes[idx].kind, NodeKind::Hidden | NodeKind::Output) {
+                self.plan.push((idx, std::mem::take(&mut incoming[idx])));
+            }
+        }
+    }
+}
+
+impl Brain for Genome {
+    type State = ();
+
+    /// Always feed-forward, `recurrent` is ignored
+    fn random(config: &GameConfig) -> Self {
+        Self::new(config.num_inputs(), OUTPUT_LAYER_SIZE)
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    fn predict(&self, inputs: &[f64], _state: &mut Self::State) -> Vec<f64> {
+        Genome::predict(self, inputs)
+    }
+
+    fn layer_values(&self, inputs: &[f64], _state: &Self::State) -> Vec<Vec<f64>> {
+        Genome::layer_values(self, inputs)
+    }
+
+    fn crossover(&self, other: &Self) -> Self {
+        Genome::crossover(self, other)
+    }
+
+    fn mutate(&mut self, rate: f32, variation: f32) {
+        Genome::mutate(self, rate, variation);
+    }
+
+    fn distance(&self, other: &Self) -> f64 {
+        Genome::distance(self, other)
+    }
+
+    fn is_compatible(&self, other: &Self) -> bool {
+        self.n_inputs == other.n_inputs && self.n_outputs == other.n_outputs
+    }
+
+    fn to_text(&self) -> String {
+        Genome::to_text(self)
+    }
+
+    fn from_text(text: &str) -> io::Result<Self> {
+        Genome::from_text(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two inputs, the bias and one output, every gene from the first input to the output
+    fn genome(genes: &[(usize, f64)]) -> Genome {
+        let connections = genes
+            .iter()
+            .map(|&(innovation, weight)| ConnectionGene {
+                innovation,
+                from: 0,
+                to: 3,
+                weight,
+                is_enabled: true,
+            })
+            .collect();
+        Genome::from_genes(2, 1, Genome::base_nodes(2, 1), connections)
+    }
+
+    fn is_acyclic(genome: &Genome) -> bool {
+        genome
+            .connections
+            .iter()
+            .all(|c| !Genome::is_reachable(&genome.connections, c.to, c.from))
+    }
+
+    #[test]
+    fn distance_counts_excess_disjoint_and_weight_terms() {
+        let a = genome(&[(0, 0.5), (1, 0.2), (3, 0.0)]);
+        let b = genome(&[(0, 0.1), (2, 0.0), (4, 0.0), (5, 0.0)]);
+        // Excess 4 and 5, disjoint 1, 2 and 3, one matching gene 0.4 apart, 4 genes
+        let expected = NEAT_EXCESS_COEFF * 2.0 / 4.0
+            + NEAT_DISJOINT_COEFF * 3.0 / 4.0
+            + NEAT_WEIGHT_COEFF * 0.4;
+        assert!((a.distance(&b) - expected).abs() < 1e-12);
+        assert!((b.distance(&a) - expected).abs() < 1e-12);
+        assert_eq!(a.distance(&a), 0.0);
+    }
+
+    #[test]
+    fn crossover_keeps_the_fitter_parents_disjoint_genes() {
+        let fitter = genome(&[(0, 0.5), (1, 0.2), (3, -0.3)]);
+        let other = genome(&[(0, 0.1), (2, 0.7), (4, 0.9)]);
+        for _ in 0..20 {
+            let child = fitter.crossover(&other);
+            let genes: Vec<(usize, f64)> = child
+                .connections
+                .iter()
+                .map(|c| (c.innovation, c.weight))
+                .collect();
+            assert_eq!(genes.len(), 3);
+            assert!(genes[0] == (0, 0.5) || genes[0] == (0, 0.1));
+            assert_eq!(genes[1..], [(1, 0.2), (3, -0.3)]);
+        }
+    }
+
+    #[test]
+    fn structural_mutations_never_create_a_cycle() {
+        let mut genome = Genome::new(3, 2);
+        for _ in 0..50 {
+            genome.add_node();
+            genome.add_connection();
+            genome.build_plan();
+            assert!(is_acyclic(&genome));
+            // Every hidden and output node gets evaluated
+            assert_eq!(genome.plan.len(), genome.num_hidden() + 2);
+        }
+        assert!(genome.num_hidden() > 0);
+    }
+
+    #[test]
+    fn text_round_trip_predicts_the_same() {
+        let mut genome = Genome::new(3, 2);
+        for _ in 0..20 {
+            genome.add_node();
+            genome.add_connection();
+        }
+        genome.mutate(0.5, 0.2);
+
+        let loaded = Genome::from_text(&genome.to_text()).unwrap();
+        assert_eq!(loaded.num_hidden(), genome.num_hidden());
+        assert_eq!(loaded.connections.len(), genome.connections.len());
+        for inputs in [[0.0, 0.5, 1.0], [1.0, -1.0, 0.25]] {
+            let (a, b) = (genome.predict(&inputs), loaded.predict(&inputs));
+            assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-12));
+        }
+        // The loaded hidden nodes don't collide with the nodes of this run
+        let original_ids: Vec<usize> = genome.nodes.iter().map(|n| n.id).collect();
+        assert!(loaded
+            .nodes
+            .iter()
+            .filter(|n| n.kind == NodeKind::Hidden)
+            .all(|n| !original_ids.contains(&n.id)));
+    }
+
+    #[test]
+    fn from_text_rejects_cycles_and_unknown_nodes() {
+        let header = "neat 2 1\nnode 10000\nnode 10001\n";
+        let cycle = "conn 10000 10001 0.5 1\nconn 10001 10000 0.5 0\n";
+        assert!(Genome::from_text(&format!("{header}{cycle}")).is_err());
+        assert!(Genome::from_text(&format!("{header}conn 10000 10000 0.5 1\n")).is_err());
+        assert!(Genome::from_text(&format!("{header}conn 0 10002 0.5 1\n")).is_err());
+        assert!(Genome::from_text(&format!("{header}conn 3 10000 0.5 1\n")).is_err());
+        assert!(Genome::from_text(&format!("{header}conn 10000 0 0.5 1\n")).is_err());
+
+        let valid = "conn 0 10000 0.5 1\nconn 10000 10001 0.5 1\nconn 10001 3 0.5 1\n";
+        let loaded = Genome::from_text(&format!("{header}{valid}")).unwrap();
+        assert_eq!(loaded.layer_values(&[0.5, 0.5]).len(), 4);
+    }
+}
@@ -1,9 +1,15 @@
 //! A simple Neural Network
-//! There is no way to train this network
-//! It can only be used for neuro-evolution
+//! Mostly used for neuro-evolution, it can also be pretrained with backpropagation
+//! Hidden layers can be recurrent (Elman), their previous output is fed back as extra inputs
+
+use std::fs;
+use std::io;
+use std::path::Path;
 
 use rand::Rng;
 
+use crate::brain::Brain;
+use crate::game::GameConfig;
 use crate::*;
 
 #[derive(Clone)]
@@ -15,17 +21,41 @@ pub struct Net {
 #[derive(Clone)]
 struct Layer {
     nodes: Vec<Vec<f64>>,
+    is_recurrent: bool,
+}
+
+/// Weights of many nets with the same shape, packed into contiguous f32 buffers
+/// Used to run a single forward pass over all the games of a stream, one layer at a time
+pub struct BatchNet {
+    shape: Vec<usize>,
+    /// One buffer per layer, the nets are back to back
+    /// with one row per node, bias first
+    layers: Vec<Vec<f32>>,
+    /// Values of the current and next layer, one row per game, kept between passes
+    current: Vec<f32>,
+    next: Vec<f32>,
 }
 
 impl Net {
     pub fn new() -> Self {
-        let layer_sizes = vec![
+        Self::with_shape(&[
             INP_LAYER_SIZE,
             HIDDEN_LAYER_SIZE,
             // HIDDEN_LAYER_SIZE,
             OUTPUT_LAYER_SIZE,
-        ];
+        ])
+    }
+
+    pub fn with_shape(layer_sizes: &[usize]) -> Self {
+        Self::build(layer_sizes, false)
+    }
+
+    /// Same as `with_shape` but every hidden layer is recurrent
+    pub fn with_recurrent_shape(layer_sizes: &[usize]) -> Self {
+        Self::build(layer_sizes, true)
+    }
 
+    fn build(layer_sizes: &[usize], is_recurrent: bool) -> Self {
         if layer_sizes.len() < 2 {
             panic!("Need at least 2 layers");
         }
@@ -39,8 +69,14 @@ impl Net {
         let first_layer_size = *layer_sizes.first().unwrap();
         let mut prev_layer_size = first_layer_size;
 
-        for &layer_size in layer_sizes[1..].iter() {
-            layers.push(Layer::new(layer_size, prev_layer_size));
+        let num_layers = layer_sizes.len() - 1;
+        for (idx, &layer_size) in layer_sizes[1..].iter().enumerate() {
+            let is_hidden = idx < num_layers - 1;
+            layers.push(Layer::new(
+                layer_size,
+                prev_layer_size,
+                is_recurrent && is_hidden,
+            ));
             prev_layer_size = layer_size;
         }
 
@@ -50,13 +86,131 @@ impl Net {
         }
     }
 
+    pub fn num_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    /// Layer sizes, inputs first
+    pub fn shape(&self) -> Vec<usize> {
+        let mut sizes = vec![self.n_inputs];
+        sizes.extend(self.layers.iter().map(|l| l.nodes.len()));
+        sizes
+    }
+
+    /// Number of weights and biases
+    pub fn num_params(&self) -> usize {
+        self.layer_num_params().iter().sum()
+    }
+
+    /// Number of weights and biases of every layer after the inputs
+    pub fn layer_num_params(&self) -> Vec<usize> {
+        self.layers
+            .iter()
+            .map(|l| l.nodes.iter().map(|n| n.len()).sum())
+            .collect()
+    }
+
+    /// All weights in a single vector, layer by layer and node by node, bias first
+    pub fn params(&self) -> Vec<f64> {
+        let mut params = Vec::with_capacity(self.num_params());
+        for layer in self.layers.iter() {
+            for node in layer.nodes.iter() {
+                params.extend_from_slice(node);
+            }
+        }
+
+        params
+    }
+
+    /// Feed-forward net from the output of `params`
+    /// None when the shape isn't valid or the count doesn't match it
+    pub fn from_params(shape: &[usize], params: &[f64]) -> Option<Self> {
+        Self::build_from_params(shape, false, params)
+    }
+
+    /// Same as `from_params` but every hidden layer is recurrent
+    pub fn from_recurrent_params(shape: &[usize], params: &[f64]) -> Option<Self> {
+        Self::build_from_params(shape, true, params)
+    }
+
+    fn build_from_params(shape: &[usize], recurrent: bool, params: &[f64]) -> Option<Self> {
+        if shape.len() < 2 || shape.contains(&0) {
+            return None;
+        }
+
+        let num_layers = shape.len() - 1;
+        let layers = shape
+            .windows(2)
+            .enumerate()
+            .map(|(idx, sizes)| {
+                let is_recurrent = recurrent && idx < num_layers - 1;
+                let num_weights = sizes[0] + 1 + if is_recurrent { sizes[1] } else { 0 };
+                Layer {
+                    nodes: vec![vec![0.0; num_weights]; sizes[1]],
+                    is_recurrent,
+                }
+            })
+            .collect();
+        let mut net = Self {
+            n_inputs: shape[0],
+            layers,
+        };
+
+        net.set_params(params).then_some(net)
+    }
+
+    /// Replaces the weights keeping the shape, false when the count doesn't match
+    pub fn set_params(&mut self, params: &[f64]) -> bool {
+        if params.len() != self.num_params() {
+            return false;
+        }
+
+        let mut values = params.iter();
+        for layer in self.layers.iter_mut() {
+            for node in layer.nodes.iter_mut() {
+                for weight in node.iter_mut() {
+                    *weight = *values.next().unwrap();
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn is_recurrent(&self) -> bool {
+        self.layers.iter().any(|l| l.is_recurrent)
+    }
+
+    /// Recurrent flag of every layer after the inputs
+    pub fn recurrent_layers(&self) -> Vec<bool> {
+        self.layers.iter().map(|l| l.is_recurrent).collect()
+    }
+
+    /// True if both nets have the same layer sizes and recurrent layers
+    pub fn is_same_shape(&self, other: &Net) -> bool {
+        self.shape() == other.shape() && self.recurrent_layers() == other.recurrent_layers()
+    }
+
+    /// Zeroed hidden state, one entry per layer, empty for feed-forward layers
+    pub fn initial_state(&self) -> Vec<Vec<f64>> {
+        self.layers
+            .iter()
+            .map(|l| {
+                if l.is_recurrent {
+                    vec![0.0; l.nodes.len()]
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect()
+    }
+
     pub fn merge(&self, other: &Net) -> Self {
-        assert_eq!(self.layers.len(), other.layers.len());
+        assert!(self.is_same_shape(other));
 
         let mut merged_layers = Vec::new();
         for i in 0..self.layers.len() {
-            let merged_layer = &self.layers[i].merge(&other.layers[i]);
-            merged_layers.push(merged_layer.clone());
+            merged_layers.push(self.layers[i].merge(&other.layers[i]));
         }
 
         Net {
@@ -65,7 +219,24 @@ impl Net {
         }
     }
 
-    pub fn predict(&self, inputs: &Vec<f64>) -> Vec<Vec<f64>> {
+    /// Prediction from a zeroed hidden state
+    pub fn predict(&self, inputs: &[f64]) -> Vec<Vec<f64>> {
+        self.predict_with_state(inputs, &mut self.initial_state())
+    }
+
+    /// Prediction that reads and updates the hidden state of the recurrent layers
+    /// The state is zeroed when it doesn't match this net
+    pub fn predict_with_state(&self, inputs: &[f64], state: &mut Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+        let is_state_valid = state.len() == self.layers.len()
+            && self
+                .layers
+                .iter()
+                .zip(state.iter())
+                .all(|(l, s)| !l.is_recurrent || s.len() == l.nodes.len());
+        if !is_state_valid {
+            *state = self.initial_state();
+        }
+
         if inputs.len() != self.n_inputs {
             panic!(
                 "Bad input size, expected {:?} but got {:?}",
@@ -75,35 +246,337 @@ impl Net {
         }
 
         let mut outputs = Vec::new();
-        outputs.push(inputs.clone());
+        outputs.push(inputs.to_vec());
         for (layer_index, layer) in self.layers.iter().enumerate() {
-            let layer_results = layer.predict(&outputs[layer_index]);
+            let layer_results = layer.predict(&outputs[layer_index], &state[layer_index]);
+            if layer.is_recurrent {
+                state[layer_index] = layer_results.clone();
+            }
             outputs.push(layer_results);
         }
 
         outputs
     }
 
+    /// One step of gradient descent towards the targets, returns the loss before the step
+    /// Binary cross entropy on the sigmoid outputs, averaged over the outputs
+    /// Recurrent layers see a zeroed memory, their feedback weights aren't trained
+    pub fn train_step(&mut self, inputs: &[f64], targets: &[f64], learning_rate: f64) -> f64 {
+        let outputs = self.predict(inputs);
+        let predicted = outputs.last().unwrap();
+        assert_eq!(predicted.len(), targets.len());
+
+        let loss = predicted
+            .iter()
+            .zip(targets.iter())
+            .map(|(p, t)| {
+                let p = p.clamp(1e-7, 1.0 - 1e-7);
+                -(t * p.ln() + (1.0 - t) * (1.0 - p).ln())
+            })
+            .sum::<f64>()
+            / targets.len() as f64;
+
+        let mut deltas: Vec<f64> = predicted
+            .iter()
+            .zip(targets.iter())
+            .map(|(p, t)| (p - t) / targets.len() as f64)
+            .collect();
+        for (layer_idx, layer) in self.layers.iter_mut().enumerate().rev() {
+            let layer_inputs = &outputs[layer_idx];
+
+            // Deltas of the previous layer, before its weights change
+            let prev_deltas: Vec<f64> = layer_inputs
+                .iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    let total: f64 = layer
+                        .nodes
+                        .iter()
+                        .zip(deltas.iter())
+                        .map(|(node, d)| node[i + 1] * d)
+                        .sum();
+                    total * a * (1.0 - a)
+                })
+                .collect();
+
+            for (node, delta) in layer.nodes.iter_mut().zip(deltas.iter()) {
+                node[0] -= learning_rate * delta;
+                for (weight, a) in node[1..].iter_mut().zip(layer_inputs.iter()) {
+                    *weight -= learning_rate * delta * a;
+                }
+            }
+            deltas = prev_deltas;
+        }
+
+        loss
+    }
+
     pub fn mutate(&mut self) {
-        self.layers.iter_mut().for_each(|l| l.mutate());
+        self.mutate_with(BRAIN_MUTATION_RATE, BRAIN_MUTATION_VARIATION);
+    }
+
+    pub fn mutate_with(&mut self, rate: f32, variation: f32) {
+        self.layers
+            .iter_mut()
+            .for_each(|l| l.mutate(rate, variation));
+    }
+
+    /// Plain text format, the first line has the layer sizes, recurrent ones end with `r`
+    /// followed by one line of weights per node, bias first and the recurrent weights last
+    pub fn to_text(&self) -> String {
+        let mut sizes = vec![self.n_inputs.to_string()];
+        for layer in self.layers.iter() {
+            let suffix = if layer.is_recurrent { "r" } else { "" };
+            sizes.push(format!("{}{}", layer.nodes.len(), suffix));
+        }
+
+        let mut lines = vec![sizes.join(" ")];
+        for layer in self.layers.iter() {
+            for node in layer.nodes.iter() {
+                let weights: Vec<String> = node.iter().map(|w| w.to_string()).collect();
+                lines.push(weights.join(" "));
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let bad_data = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| bad_data("Missing layer sizes"))?
+            .split_whitespace()
+            .collect();
+        let recurrent: Vec<bool> = header.iter().map(|v| v.ends_with('r')).collect();
+        let sizes = header
+            .iter()
+            .map(|v| v.trim_end_matches('r').parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| bad_data("Bad layer sizes"))?;
+        if sizes.len() < 2 || sizes.contains(&0) {
+            return Err(bad_data("Need at least 2 non empty layers"));
+        }
+        if recurrent[0] || recurrent[sizes.len() - 1] {
+            return Err(bad_data("Only hidden layers can be recurrent"));
+        }
+
+        let mut layers = Vec::new();
+        for (window, &is_recurrent) in sizes.windows(2).zip(recurrent[1..].iter()) {
+            let num_weights = window[0] + 1 + if is_recurrent { window[1] } else { 0 };
+            let mut nodes = Vec::new();
+            for _ in 0..window[1] {
+                let node = lines
+                    .next()
+                    .ok_or_else(|| bad_data("Missing node weights"))?
+                    .split_whitespace()
+                    .map(|v| v.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| bad_data("Bad node weights"))?;
+                if node.len() != num_weights {
+                    return Err(bad_data("Wrong number of node weights"));
+                }
+                nodes.push(node);
+            }
+            layers.push(Layer {
+                nodes,
+                is_recurrent,
+            });
+        }
+
+        Ok(Self {
+            n_inputs: sizes[0],
+            layers,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Root mean squared difference between the weights of two nets
+    pub fn distance(&self, other: &Net) -> f64 {
+        assert!(self.is_same_shape(other));
+
+        let total: f64 = self
+            .params()
+            .iter()
+            .zip(other.params().iter())
+            .map(|(w1, w2)| (w1 - w2).powi(2))
+            .sum();
+        (total / self.num_params() as f64).sqrt()
+    }
+}
+
+impl Default for Net {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Brain for Net {
+    type State = Vec<Vec<f64>>;
+
+    fn random(config: &GameConfig) -> Self {
+        let shape = [config.num_inputs(), HIDDEN_LAYER_SIZE, OUTPUT_LAYER_SIZE];
+        if config.recurrent {
+            Self::with_recurrent_shape(&shape)
+        } else {
+            Self::with_shape(&shape)
+        }
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    fn predict(&self, inputs: &[f64], state: &mut Self::State) -> Vec<f64> {
+        self.predict_with_state(inputs, state).pop().unwrap()
+    }
+
+    fn layer_values(&self, inputs: &[f64], state: &Self::State) -> Vec<Vec<f64>> {
+        self.predict_with_state(inputs, &mut state.clone())
+    }
+
+    fn recurrent_layers(&self) -> Vec<bool> {
+        Net::recurrent_layers(self)
+    }
+
+    fn crossover(&self, other: &Self) -> Self {
+        self.merge(other)
+    }
+
+    fn mutate(&mut self, rate: f32, variation: f32) {
+        self.mutate_with(rate, variation);
+    }
+
+    fn distance(&self, other: &Self) -> f64 {
+        Net::distance(self, other)
+    }
+
+    fn is_compatible(&self, other: &Self) -> bool {
+        self.is_same_shape(other)
+    }
+
+    fn to_text(&self) -> String {
+        Net::to_text(self)
+    }
+
+    fn from_text(text: &str) -> io::Result<Self> {
+        Net::from_text(text)
+    }
+
+    fn params(&self) -> Option<Vec<f64>> {
+        Some(Net::params(self))
+    }
+
+    fn with_params(&self, params: &[f64]) -> Option<Self> {
+        let mut net = self.clone();
+        net.set_params(params).then_some(net)
+    }
+
+    fn train_step(&mut self, inputs: &[f64], targets: &[f64], learning_rate: f64) -> Option<f64> {
+        Some(Net::train_step(self, inputs, targets, learning_rate))
+    }
+
+    fn batch(brains: &[&Self]) -> Option<BatchNet> {
+        BatchNet::from_nets(brains)
+    }
+}
+
+impl BatchNet {
+    /// None when there are no nets, their shapes differ or they are recurrent
+    pub fn from_nets(nets: &[&Net]) -> Option<Self> {
+        let shape = nets.first()?.shape();
+        if nets.iter().any(|n| n.is_recurrent() || n.shape() != shape) {
+            return None;
+        }
+
+        let mut layers = Vec::new();
+        for (layer_idx, sizes) in shape.windows(2).enumerate() {
+            let mut weights = Vec::with_capacity(nets.len() * sizes[1] * (sizes[0] + 1));
+            for net in nets.iter() {
+                for node in net.layers[layer_idx].nodes.iter() {
+                    weights.extend(node.iter().map(|&w| w as f32));
+                }
+            }
+            layers.push(weights);
+        }
+
+        Some(Self {
+            shape,
+            layers,
+            current: Vec::new(),
+            next: Vec::new(),
+        })
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.shape[0]
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        *self.shape.last().unwrap()
+    }
+
+    /// Output layer of the nets at `indices`, `inputs` has one row of inputs per index
+    /// The result has one row of `num_outputs` values per index
+    /// Every layer is computed for all the rows before moving to the next one
+    pub fn predict(&mut self, indices: &[usize], inputs: &[f32]) -> &[f32] {
+        assert_eq!(inputs.len(), indices.len() * self.num_inputs());
+
+        self.current.clear();
+        self.current.extend_from_slice(inputs);
+        for (weights, sizes) in self.layers.iter().zip(self.shape.windows(2)) {
+            let (n_in, n_out) = (sizes[0], sizes[1]);
+            let stride = n_in + 1;
+            let net_size = n_out * stride;
+
+            self.next.clear();
+            for (row, &net_idx) in self.current.chunks_exact(n_in).zip(indices.iter()) {
+                let net_weights = &weights[net_idx * net_size..(net_idx + 1) * net_size];
+                for node in net_weights.chunks_exact(stride) {
+                    let total = node[0]
+                        + node[1..]
+                            .iter()
+                            .zip(row.iter())
+                            .map(|(w, v)| w * v)
+                            .sum::<f32>();
+                    self.next.push(1f32 / (1f32 + (-total).exp()));
+                }
+            }
+            std::mem::swap(&mut self.current, &mut self.next);
+        }
+
+        &self.current
     }
 }
 
 impl Layer {
-    fn new(layer_size: usize, prev_layer_size: usize) -> Self {
+    fn new(layer_size: usize, prev_layer_size: usize, is_recurrent: bool) -> Self {
         let mut nodes: Vec<Vec<f64>> = Vec::new();
         let mut rng = rand::thread_rng();
+        let num_recurrent = if is_recurrent { layer_size } else { 0 };
 
         for _ in 0..layer_size {
             let mut node: Vec<f64> = Vec::new();
-            for _ in 0..prev_layer_size + 1 {
+            for _ in 0..prev_layer_size + 1 + num_recurrent {
                 let random_weight: f64 = rng.gen_range(-1.0f64..1.0f64);
                 node.push(random_weight);
             }
             nodes.push(node);
         }
 
-        Self { nodes }
+        Self {
+            nodes,
+            is_recurrent,
+        }
     }
 
     fn merge(&self, other: &Layer) -> Self {
@@ -120,28 +593,38 @@ impl Layer {
             nodes.push(merged_node);
         }
 
-        Self { nodes }
+        Self {
+            nodes,
+            is_recurrent: self.is_recurrent,
+        }
     }
 
-    fn predict(&self, inputs: &Vec<f64>) -> Vec<f64> {
+    /// `state` is the previous output of a recurrent layer, ignored otherwise
+    fn predict(&self, inputs: &[f64], state: &[f64]) -> Vec<f64> {
+        let values = if self.is_recurrent {
+            [inputs, state].concat()
+        } else {
+            inputs.to_vec()
+        };
+
         let mut layer_results = Vec::new();
         for node in self.nodes.iter() {
-            layer_results.push(self.sigmoid(self.dot_prod(&node, &inputs)));
+            layer_results.push(self.sigmoid(self.dot_prod(node, &values)));
         }
 
         layer_results
     }
 
-    fn mutate(&mut self) {
+    fn mutate(&mut self, rate: f32, variation: f32) {
         let mut rng = rand::thread_rng();
 
         for n in self.nodes.iter_mut() {
             for val in n.iter_mut() {
-                if rng.gen_range(0.0..1.0) >= BRAIN_MUTATION_RATE {
+                if rng.gen_range(0.0..1.0) >= rate {
                     continue;
                 }
 
-                *val += rng.gen_range(-BRAIN_MUTATION_VARIATION..BRAIN_MUTATION_VARIATION) as f64;
+                *val += rng.gen_range(-variation..variation) as f64;
                 if *val > 1.0 || *val < -1.0 {
                     let random_weight = rng.gen_range(-1.0f64..1.0f64);
                     *val = random_weight;
@@ -150,7 +633,7 @@ impl Layer {
         }
     }
 
-    fn dot_prod(&self, node: &Vec<f64>, values: &Vec<f64>) -> f64 {
+    fn dot_prod(&self, node: &[f64], values: &[f64]) -> f64 {
         let mut it = node.iter();
         let mut total = *it.next().unwrap();
         for (weight, value) in it.zip(values.iter()) {
@@ -164,3 +647,122 @@ impl Layer {
         1f64 / (1f64 + (-y).exp())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_round_trip() {
+        for net in [
+            Net::with_shape(&[3, 4, 2]),
+            Net::with_recurrent_shape(&[3, 4, 5, 2]),
+        ] {
+            let loaded = if net.is_recurrent() {
+                Net::from_recurrent_params(&net.shape(), &net.params())
+            } else {
+                Net::from_params(&net.shape(), &net.params())
+            }
+            .unwrap();
+            assert_eq!(loaded.shape(), net.shape());
+            assert_eq!(loaded.recurrent_layers(), net.recurrent_layers());
+            assert_eq!(loaded.params(), net.params());
+        }
+    }
+
+    #[test]
+    fn from_params_rejects_bad_shapes_and_counts() {
+        let net = Net::with_shape(&[3, 4, 2]);
+        assert!(Net::from_params(&[3], &[]).is_none());
+        assert!(Net::from_params(&[3, 0, 2], &net.params()).is_none());
+        assert!(Net::from_params(&[3, 4, 2], &net.params()[1..]).is_none());
+        assert!(Net::from_recurrent_params(&[3, 4, 2], &net.params()).is_none());
+    }
+
+    #[test]
+    fn batch_predict_matches_net_predict() {
+        let nets: Vec<Net> = (0..4).map(|_| Net::with_shape(&[3, 5, 4, 2])).collect();
+        let refs: Vec<&Net> = nets.iter().collect();
+        let mut batch = BatchNet::from_nets(&refs).unwrap();
+
+        // Out of order and repeated nets, as when some games are over
+        let indices = [3, 0, 2, 0];
+        let rows = [
+            [0.0, 0.5, 1.0],
+            [1.0, 0.0, 0.25],
+            [0.3, 0.6, 0.9],
+            [1.0, 1.0, 0.0],
+        ];
+        let inputs: Vec<f32> = rows.iter().flatten().map(|&v| v as f32).collect();
+        let outputs = batch.predict(&indices, &inputs).to_vec();
+        assert_eq!(outputs.len(), indices.len() * 2);
+        for ((&net_idx, row), out) in indices.iter().zip(rows.iter()).zip(outputs.chunks(2)) {
+            let expected = nets[net_idx].predict(row).pop().unwrap();
+            for (&a, &b) in out.iter().zip(expected.iter()) {
+                assert!((a as f64 - b).abs() < 1e-5);
+            }
+        }
+
+        assert!(BatchNet::from_nets(&[&Net::with_recurrent_shape(&[3, 4, 2])]).is_none());
+        assert!(BatchNet::from_nets(&[&nets[0], &Net::with_shape(&[3, 4, 2])]).is_none());
+    }
+
+    #[test]
+    fn train_step_follows_the_loss_gradient() {
+        let net = Net::with_shape(&[3, 4, 2]);
+        let (inputs, targets) = ([0.2, -0.5, 0.9], [1.0, 0.0]);
+        let loss_at = |params: &[f64]| {
+            let mut net = Net::from_params(&net.shape(), params).unwrap();
+            net.train_step(&inputs, &targets, 0.0)
+        };
+
+        let learning_rate = 1e-3;
+        let mut stepped = net.clone();
+        stepped.train_step(&inputs, &targets, learning_rate);
+
+        let eps = 1e-6;
+        let params = net.params();
+        for (idx, new_param) in stepped.params().iter().enumerate() {
+            let gradient = (params[idx] - new_param) / learning_rate;
+            let (mut plus, mut minus) = (params.clone(), params.clone());
+            plus[idx] += eps;
+            minus[idx] -= eps;
+            let numeric = (loss_at(&plus) - loss_at(&minus)) / (2.0 * eps);
+            assert!(
+                (gradient - numeric).abs() < 1e-6,
+                "param {}: {} vs {}",
+                idx,
+                gradient,
+                numeric
+            );
+        }
+    }
+
+    #[test]
+    fn recurrent_memory_carries_across_steps() {
+        let net = Net::with_recurrent_shape(&[3, 4, 2]);
+        let inputs = [0.2, 0.7, -0.4];
+        let mut state = net.initial_state();
+        let first = net.predict_with_state(&inputs, &mut state).pop().unwrap();
+        let second = net.predict_with_state(&inputs, &mut state).pop().unwrap();
+        assert_ne!(first, second);
+        assert_eq!(net.predict(&inputs).pop().unwrap(), first);
+
+        let feed_forward = Net::with_shape(&[3, 4, 2]);
+        let mut state = feed_forward.initial_state();
+        let first = feed_forward.predict_with_state(&inputs, &mut state);
+        assert_eq!(feed_forward.predict_with_state(&inputs, &mut state), first);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let net = Net::with_recurrent_shape(&[3, 4, 2]);
+        let loaded = Net::from_text(&net.to_text()).unwrap();
+        assert_eq!(loaded.recurrent_layers(), vec![true, false]);
+        assert_eq!(loaded.params(), net.params());
+        assert_eq!(loaded.to_text(), net.to_text());
+
+        assert!(Net::from_text("3 4r\n").is_err());
+        assert!(Net::from_text("3 1 1\n0 0 0 0\n").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Novelty Search
+//! Scores games by how different their behaviour is from the rest of the stream
+//! Keeps an archive of novel behaviours seen in the past generations
+
+use std::collections::VecDeque;
+
+use crate::*;
+
+pub struct NoveltyArchive {
+    behaviours: VecDeque<Vec<f32>>,
+}
+
+impl NoveltyArchive {
+    pub fn new() -> Self {
+        Self {
+            behaviours: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.behaviours.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.behaviours.is_empty()
+    }
+
+    /// Novelty of every behaviour, the mean distance to its k nearest neighbours
+    /// among the current behaviours and the archive
+    pub fn evaluate(&self, behaviours: &[Vec<f32>]) -> Vec<f32> {
+        let mut novelty = Vec::with_capacity(behaviours.len());
+
+        for (idx, behaviour) in behaviours.iter().enumerate() {
+            let mut distances: Vec<f32> = behaviours
+                .iter()
+                .enumerate()
+                .filter(|(other_idx, _)| *other_idx != idx)
+                .map(|(_, other)| behaviour_distance(behaviour, other))
+                .chain(
+                    self.behaviours
+                        .iter()
+                        .map(|b| behaviour_distance(behaviour, b)),
+                )
+                .collect();
+
+            let k = NOVELTY_K_NEAREST.min(distances.len());
+            if k == 0 {
+                novelty.push(0.0);
+                continue;
+            }
+
+            distances.select_nth_unstable_by(k - 1, |a, b| {
+                a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            novelty.push(distances[..k].iter().sum::<f32>() / k as f32);
+        }
+
+        novelty
+    }
+
+    /// Archives the most novel behaviours of a generation, dropping the oldest when full
+    pub fn update(&mut self, behaviours: &[Vec<f32>], novelty: &[f32]) {
+        let mut order: Vec<usize> = (0..behaviours.len()).collect();
+        order.sort_by(|&a, &b| {
+            novelty[b]
+                .partial_cmp(&novelty[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        for &idx in order.iter().take(NOVELTY_ARCHIVE_ADD_PER_GEN) {
+            self.behaviours.push_back(behaviours[idx].clone());
+        }
+        while self.behaviours.len() > NOVELTY_ARCHIVE_MAX_SIZE {
+            self.behaviours.pop_front();
+        }
+    }
+}
+
+impl Default for NoveltyArchive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn behaviour_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(values: impl Iterator<Item = f32>) -> Vec<Vec<f32>> {
+        values.map(|v| vec![v, 0.0]).collect()
+    }
+
+    #[test]
+    fn evaluate_averages_the_k_nearest_distances() {
+        let archive = NoveltyArchive::new();
+        let behaviours = line((0..=NOVELTY_K_NEAREST + 4).map(|v| v as f32));
+        let novelty = archive.evaluate(&behaviours);
+
+        // The first point only sees its k nearest, at distances 1 to k
+        let expected = (NOVELTY_K_NEAREST + 1) as f32 / 2.0;
+        assert!((novelty[0] - expected).abs() < 1e-4);
+        assert!(novelty[behaviours.len() / 2] < novelty[0]);
+
+        assert_eq!(archive.evaluate(&[vec![1.0, 2.0]]), vec![0.0]);
+    }
+
+    #[test]
+    fn evaluate_counts_the_archive() {
+        let mut archive = NoveltyArchive::new();
+        let behaviours = line([0.0, 4.0].into_iter());
+        assert_eq!(archive.evaluate(&behaviours), vec![4.0, 4.0]);
+
+        archive.behaviours.push_back(vec![4.0, 3.0]);
+        assert_eq!(archive.evaluate(&behaviours), vec![4.5, 3.5]);
+    }
+
+    #[test]
+    fn update_archives_the_most_novel_and_drops_the_oldest() {
+        let mut archive = NoveltyArchive::new();
+        let num_behaviours = NOVELTY_ARCHIVE_ADD_PER_GEN * 2;
+        let behaviours = line((0..num_behaviours).map(|v| v as f32));
+        let novelty: Vec<f32> = (0..num_behaviours).map(|v| v as f32).collect();
+        archive.update(&behaviours, &novelty);
+
+        assert_eq!(archive.len(), NOVELTY_ARCHIVE_ADD_PER_GEN);
+        assert_eq!(archive.behaviours[0], behaviours[num_behaviours - 1]);
+        assert!(archive
+            .behaviours
+            .iter()
+            .all(|b| b[0] >= NOVELTY_ARCHIVE_ADD_PER_GEN as f32));
+
+        for _ in 0..NOVELTY_ARCHIVE_MAX_SIZE {
+            archive.update(&line([-1.0].into_iter()), &[1.0]);
+        }
+        assert_eq!(archive.len(), NOVELTY_ARCHIVE_MAX_SIZE);
+        assert!(archive.behaviours.iter().all(|b| b[0] == -1.0));
+    }
+}
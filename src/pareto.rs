@@ -0,0 +1,237 @@
+//! Multi-objective selection
+//! NSGA-II style non-dominated sorting and crowding distance
+//! Objectives are picked with `PARETO_OBJECTIVES`
+
+use crate::brain::Brain;
+use crate::game::Game;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Objective {
+    /// Length grown since the start, see `Game::score`, maximized
+    Score,
+    /// Number of steps played, maximized
+    Survival,
+    /// Steps taken per food eaten, minimized
+    Efficiency,
+}
+
+impl Objective {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Score => "score",
+            Self::Survival => "steps",
+            Self::Efficiency => "steps/food",
+        }
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        !matches!(self, Self::Efficiency)
+    }
+
+    pub fn value<B: Brain>(&self, game: &Game<B>) -> f32 {
+        match self {
+            Self::Score => game.score() as f32,
+            Self::Survival => game.num_steps() as f32,
+            Self::Efficiency => {
+                let num_food = game.num_food_eaten();
+                if num_food == 0 {
+                    return f32::MAX;
+                }
+                game.num_steps() as f32 / num_food as f32
+            }
+        }
+    }
+}
+
+/// Raw objective values of a game, in the order of `PARETO_OBJECTIVES`
+pub fn objectives<B: Brain>(game: &Game<B>) -> Vec<f32> {
+    PARETO_OBJECTIVES.iter().map(|o| o.value(game)).collect()
+}
+
+/// True if `a` is at least as good as `b` in every objective and better in one
+pub fn dominates(a: &[f32], b: &[f32]) -> bool {
+    let mut is_better = false;
+    for ((objective, va), vb) in PARETO_OBJECTIVES.iter().zip(a.iter()).zip(b.iter()) {
+        let (va, vb) = if objective.is_maximized() {
+            (*va, *vb)
+        } else {
+            (-*va, -*vb)
+        };
+
+        if va < vb {
+            return false;
+        }
+        if va > vb {
+            is_better = true;
+        }
+    }
+
+    is_better
+}
+
+/// Splits points into fronts, the first front is the pareto front
+pub fn non_dominated_sort(points: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    let mut dominated_by: Vec<Vec<usize>> = vec![Vec::new(); points.len()];
+    let mut num_dominators = vec![0; points.len()];
+    let mut fronts = Vec::new();
+    let mut current = Vec::new();
+
+    for i in 0..points.len() {
+        for j in (i + 1)..points.len() {
+            if dominates(&points[i], &points[j]) {
+                dominated_by[i].push(j);
+                num_dominators[j] += 1;
+            } else if dominates(&points[j], &points[i]) {
+                dominated_by[j].push(i);
+                num_dominators[i] += 1;
+            }
+        }
+    }
+    for (i, &count) in num_dominators.iter().enumerate() {
+        if count == 0 {
+            current.push(i);
+        }
+    }
+
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for &i in current.iter() {
+            for &j in dominated_by[i].iter() {
+                num_dominators[j] -= 1;
+                if num_dominators[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+
+        fronts.push(current);
+        current = next;
+    }
+
+    fronts
+}
+
+/// Crowding distance of every point in a front, boundary points get infinity
+pub fn crowding_distance(points: &[Vec<f32>], front: &[usize]) -> Vec<f32> {
+    let mut distances = vec![0.0; front.len()];
+    if front.len() <= 2 {
+        return vec![f32::INFINITY; front.len()];
+    }
+
+    for (objective, _) in PARETO_OBJECTIVES.iter().enumerate() {
+        let value = |i: usize| points[front[i]][objective];
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| {
+            value(a)
+                .partial_cmp(&value(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let first = order[0];
+        let last = order[front.len() - 1];
+        let (min, max) = (value(first), value(last));
+        distances[first] = f32::INFINITY;
+        distances[last] = f32::INFINITY;
+        if max - min <= 0.0 {
+            continue;
+        }
+
+        for w in order.windows(3) {
+            distances[w[1]] += (value(w[2]) - value(w[0])) / (max - min);
+        }
+    }
+
+    distances
+}
+
+/// Indices of all points, best first
+/// Sorted by front and then by crowding distance within the front
+pub fn crowded_order(points: &[Vec<f32>]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(points.len());
+    for front in non_dominated_sort(points) {
+        let distances = crowding_distance(points, &front);
+        let mut ranked: Vec<(usize, f32)> = front.into_iter().zip(distances).collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        order.extend(ranked.into_iter().map(|(idx, _)| idx));
+    }
+
+    order
+}
+
+/// Unique objective values of the non-dominated points
+pub fn pareto_front(points: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let mut front: Vec<Vec<f32>> = Vec::new();
+    if let Some(first) = non_dominated_sort(points).first() {
+        for &idx in first.iter() {
+            if !front.contains(&points[idx]) {
+                front.push(points[idx].clone());
+            }
+        }
+    }
+
+    front.sort_by(|a, b| b[0].partial_cmp(&a[0]).unwrap_or(std::cmp::Ordering::Equal));
+    front
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hand-checked against the default objectives: score and steps maximized, steps/food minimized
+    fn points() -> Vec<Vec<f32>> {
+        vec![
+            vec![5.0, 100.0, 20.0],
+            vec![3.0, 80.0, 30.0],
+            vec![6.0, 50.0, 10.0],
+            vec![2.0, 40.0, 40.0],
+            vec![1.0, 200.0, 50.0],
+        ]
+    }
+
+    #[test]
+    fn dominates_needs_one_strictly_better_objective() {
+        let points = points();
+        assert!(dominates(&points[0], &points[1]));
+        assert!(!dominates(&points[1], &points[0]));
+        assert!(!dominates(&points[0], &points[2]));
+        assert!(!dominates(&points[0], &points[0]));
+        assert!(dominates(&[5.0, 100.0, 10.0], &points[0]));
+    }
+
+    #[test]
+    fn non_dominated_sort_splits_fronts() {
+        let mut fronts = non_dominated_sort(&points());
+        fronts.iter_mut().for_each(|f| f.sort());
+        assert_eq!(fronts, vec![vec![0, 2, 4], vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn crowding_distance_sums_normalized_gaps() {
+        let distances = crowding_distance(&points(), &[0, 2, 4]);
+        assert_eq!(distances[0], 3.0);
+        assert!(distances[1].is_infinite() && distances[2].is_infinite());
+        assert!(crowding_distance(&points(), &[1, 3])
+            .iter()
+            .all(|d| d.is_infinite()));
+    }
+
+    #[test]
+    fn crowded_order_ranks_by_front_then_distance() {
+        assert_eq!(crowded_order(&points()), vec![2, 4, 0, 1, 3]);
+    }
+
+    #[test]
+    fn pareto_front_is_unique_and_sorted_by_first_objective() {
+        let mut points = points();
+        points.push(points[2].clone());
+        assert_eq!(
+            pareto_front(&points),
+            vec![
+                vec![6.0, 50.0, 10.0],
+                vec![5.0, 100.0, 20.0],
+                vec![1.0, 200.0, 50.0]
+            ]
+        );
+    }
+}
@@ -1,92 +1,368 @@
 //! Population
 //! Handles multiples streams (islands) of neuro-evoloving agents
-//! Also responsible for Island Rejuvenation
+//! Also responsible for migration between the streams
 
+use std::io;
+use std::path::Path;
 use std::time::Instant;
 
-use rand::Rng;
-
-use crate::stream::Stream;
+use crate::brain::Brain;
+use crate::curriculum::CurriculumStage;
+use crate::es;
+use crate::eval::{self, EvalResult};
+use crate::game::{Game, GameConfig, GameCounts};
+use crate::hall_of_fame::{self, HallOfFame};
+use crate::migration::MigrationTopology;
+use crate::pareto;
+use crate::species::SpeciesSummary;
+use crate::stream::{SeedFill, StagnationResponse, Stream, StreamConfig};
+use crate::train;
 use crate::*;
 
 use self::nn::Net;
 
-pub struct Population {
+pub struct Population<B: Brain = Net> {
+    gen_count: usize,
     gen_start_ts: Instant,
-    streams: Vec<Stream>,
+    streams: Vec<Stream<B>>,
+    hall_of_fame: HallOfFame<B>,
+    gen_validation: Option<ValidationSummary>,
 }
 
-pub struct GenerationSummary {
+pub struct GenerationSummary<B: Brain = Net> {
     pub time_elapsed_secs: f32,
     pub max_score: usize,
-    pub best_net: Option<Net>,
+    pub counts: GameCounts,
+    pub best_net: Option<B>,
+    pub best_game_config: Option<GameConfig>,
+    pub best_avg_score: f32,
+    /// Best candidate of this generation on the validation seeds
+    pub validation: Option<ValidationSummary>,
+    pub species: Vec<Vec<SpeciesSummary>>,
+    pub pareto_front: Vec<Vec<f32>>,
+    /// Set by the simulation, None without a curriculum
+    pub curriculum_stage: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationSummary {
+    pub train_score: usize,
+    pub eval: EvalResult,
+    pub stream: usize,
 }
 
-impl Population {
-    pub fn new() -> Self {
+impl<B: Brain> Population<B> {
+    /// Seeded from `SEED_BRAINS_DIR` when it's set, random otherwise or when it can't be read
+    /// Fails when a level file of the stream configs can't be loaded
+    pub fn new() -> io::Result<Self> {
+        if let Some(dir) = SEED_BRAINS_DIR {
+            match Self::from_dir(Path::new(dir), SEED_FILL) {
+                Ok(pop) => return Ok(pop),
+                Err(e) => println!("Failed to load the seed brains: {}", e),
+            }
+        }
+
+        Ok(Self::with_configs(default_configs()?))
+    }
+
+    /// Random brains, or a pretrained brain and its mutated copies when `PRETRAIN_ENABLED`
+    /// Level files of the game configs are expected to be loaded, see `GameConfig::load_level`
+    pub fn with_configs(configs: Vec<StreamConfig>) -> Self {
+        let pretrained: Vec<B> = if PRETRAIN_ENABLED {
+            pretrain_brains(&configs)
+        } else {
+            Vec::new()
+        };
+
+        let streams = configs
+            .into_iter()
+            .map(|config| {
+                let reference = B::random(&config.game);
+                match pretrained.iter().find(|b| b.is_compatible(&reference)) {
+                    Some(brain) => {
+                        Stream::with_brains(config, std::slice::from_ref(brain), SeedFill::Mutated)
+                    }
+                    None => Stream::with_config(config),
+                }
+            })
+            .collect();
+        Self::with_streams(streams)
+    }
+
+    /// Every stream starts from the brains saved in `dir` that can play its game config
+    /// A stream without any starts from random brains, files that don't parse are skipped
+    /// Evolution strategies saved along with the brains are resumed
+    pub fn from_dir(dir: &Path, fill: SeedFill) -> io::Result<Self> {
+        let brains: Vec<B> = hall_of_fame::load_brains(dir)?;
         let mut streams = Vec::new();
-        for _ in 0..NUM_STREAMS {
-            streams.push(Stream::new());
+        for (idx, config) in default_configs()?.into_iter().enumerate() {
+            let Some(mut stream) = Stream::from_saved(config.clone(), &brains, fill) else {
+                println!(
+                    "No brains for stream {} in {}, starting it from random brains",
+                    idx,
+                    dir.display()
+                );
+                streams.push(Stream::with_config(config));
+                continue;
+            };
+
+            match stream.load_strategy(&dir.join(es::strategy_file_name(idx))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            streams.push(stream);
         }
 
+        Ok(Self::with_streams(streams))
+    }
+
+    fn with_streams(streams: Vec<Stream<B>>) -> Self {
         Self {
             streams,
+            gen_count: 0,
+            hall_of_fame: HallOfFame::new(),
+            gen_validation: None,
             gen_start_ts: Instant::now(),
         }
     }
 
-    pub fn update(&mut self) -> usize {
-        let mut games_alive = NUM_GAMES_PER_STREAM * NUM_STREAMS;
-
+    pub fn update(&mut self) -> GameCounts {
+        let mut counts = GameCounts::default();
         for stream in self.streams.iter_mut() {
-            games_alive -= stream.update();
+            counts += stream.update();
         }
 
-        games_alive
+        counts
+    }
+
+    pub fn counts(&self) -> GameCounts {
+        let mut counts = GameCounts::default();
+        for stream in self.streams.iter() {
+            counts += stream.counts();
+        }
+
+        counts
     }
 
     pub fn reset(&mut self) {
         self.gen_start_ts = Instant::now();
-        let mut nets = Vec::new();
+        self.gen_count += 1;
 
-        // Streams reset
         for stream in self.streams.iter_mut() {
-            let best_net = stream.reset();
-            nets.push(best_net);
+            stream.update_stagnation();
         }
+        self.migrate();
+        for stream in self.streams.iter_mut() {
+            stream.handle_stagnation();
+            stream.reset();
+        }
+    }
 
-        // No Streams to cross
-        if self.streams.len() <= 1 {
+    /// Island migration, runs every `MIGRATION_INTERVAL_GENS` generations
+    /// and for stagnant streams when `StagnationResponse::Migration` is enabled
+    fn migrate(&mut self) {
+        let num_streams = self.streams.len();
+        if num_streams <= 1 {
             return;
         }
 
-        // Streams crossing
-        let mut rng = rand::thread_rng();
+        let is_migration_gen =
+            MIGRATION_INTERVAL_GENS > 0 && self.gen_count % MIGRATION_INTERVAL_GENS == 0;
+        let is_stagnation_migration =
+            STREAM_STAGNATION_RESPONSES.contains(&StagnationResponse::Migration);
+        let targets: Vec<usize> = (0..num_streams)
+            .filter(|&idx| {
+                is_migration_gen || (is_stagnation_migration && self.streams[idx].is_stagnant())
+            })
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        self.send_migrants(&targets, MIGRATION_TOPOLOGY);
+    }
+
+    /// Every target receives the fittest games of its sources in the topology
+    /// Sources are drawn once per target, a random topology would name others on every call
+    fn send_migrants(&mut self, targets: &[usize], topology: MigrationTopology) {
+        let num_streams = self.streams.len();
+        let plan: Vec<(usize, Vec<usize>)> = targets
+            .iter()
+            .map(|&idx| (idx, topology.sources(idx, num_streams)))
+            .collect();
+
+        // Picked before any stream receives migrants, only from the streams that send some
+        let mut migrants: Vec<Option<Vec<Game<B>>>> = vec![None; num_streams];
+        for &source in plan.iter().flat_map(|(_, sources)| sources.iter()) {
+            if migrants[source].is_none() {
+                migrants[source] =
+                    Some(self.streams[source].get_fittest_games(MIGRATION_NUM_MIGRANTS));
+            }
+        }
+
+        for (idx, sources) in plan.iter() {
+            let incoming: Vec<Game<B>> = sources
+                .iter()
+                .flat_map(|&source| migrants[source].iter().flatten())
+                .cloned()
+                .collect();
+            self.streams[*idx].inject(&incoming, MIGRATION_REPLACEMENT);
+        }
+    }
+
+    /// Every stream and the hall of fame move to the stage's board
+    pub fn apply_curriculum_stage(&mut self, stage: &CurriculumStage) {
         for stream in self.streams.iter_mut() {
-            if !stream.is_local_maximum() {
-                continue;
+            stream.apply_curriculum_stage(stage);
+        }
+        self.hall_of_fame.apply_curriculum_stage(stage);
+    }
+
+    pub fn hall_of_fame(&self) -> &HallOfFame<B> {
+        &self.hall_of_fame
+    }
+
+    /// Re-runs the fittest games of every stream on the held-out validation seeds
+    /// and offers them to the hall of fame
+    /// Call once all games of the generation are complete
+    pub fn validate_generation(&mut self) {
+        let mut validated = Vec::new();
+        for (idx, stream) in self.streams.iter().enumerate() {
+            for game in stream.get_fittest_games(VALIDATION_CANDIDATES) {
+                let eval = eval::validate(&game.brain, &game.config);
+                self.hall_of_fame.consider(&game, eval, self.gen_count, idx);
+                validated.push(ValidationSummary {
+                    train_score: game.score(),
+                    eval,
+                    stream: idx,
+                });
             }
+        }
 
-            stream.inject(&nets[rng.gen_range(0..nets.len())]);
+        validated.sort_by(|a, b| {
+            b.eval
+                .mean
+                .partial_cmp(&a.eval.mean)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.gen_validation = validated.into_iter().next();
+    }
+
+    /// The hall of fame along with the evolution strategy of every stream
+    pub fn save_hall_of_fame(&self, dir: &Path) -> io::Result<()> {
+        self.hall_of_fame.save(dir)?;
+        for (idx, stream) in self.streams.iter().enumerate() {
+            stream.save_strategy(&dir.join(es::strategy_file_name(idx)))?;
         }
+
+        Ok(())
     }
 
-    pub fn get_gen_summary(&self) -> GenerationSummary {
+    pub fn get_gen_summary(&self) -> GenerationSummary<B> {
         let mut max_score = 0;
-        let mut best_net = None;
+        let mut species = Vec::new();
+        let mut front_candidates = Vec::new();
 
         for stream in self.streams.iter() {
-            let (stream_score, stream_net) = stream.get_stream_summary();
-            if stream_score > max_score {
-                max_score = stream_score;
-                best_net = stream_net;
-            }
+            species.push(stream.get_species_summary());
+            front_candidates.extend(stream.get_pareto_front());
+            max_score = max_score.max(stream.get_max_score());
         }
 
+        let best = self.hall_of_fame.best();
         GenerationSummary {
             max_score,
+            counts: self.counts(),
             time_elapsed_secs: self.gen_start_ts.elapsed().as_secs_f32(),
-            best_net,
+            best_net: best.map(|e| e.net.clone()),
+            best_game_config: best.map(|e| e.game_config.clone()),
+            best_avg_score: best.map(|e| e.eval.mean).unwrap_or(0.0),
+            validation: self.gen_validation.clone(),
+            species,
+            pareto_front: pareto::pareto_front(&front_candidates),
+            curriculum_stage: None,
+        }
+    }
+}
+
+/// `STREAM_CONFIGS`, or `NUM_STREAMS` default streams when it's empty, with their levels loaded
+fn default_configs() -> io::Result<Vec<StreamConfig>> {
+    let mut configs = if STREAM_CONFIGS.is_empty() {
+        vec![StreamConfig::default(); NUM_STREAMS]
+    } else {
+        STREAM_CONFIGS.to_vec()
+    };
+    for config in configs.iter_mut() {
+        config.game.load_level()?;
+    }
+
+    Ok(configs)
+}
+
+/// One pretrained brain for each kind of brain the streams play with
+fn pretrain_brains<B: Brain>(configs: &[StreamConfig]) -> Vec<B> {
+    let recorded = match train::load_demos(Path::new(PRETRAIN_DEMOS_FILE)) {
+        Ok(demos) => demos,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                println!("Failed to load the recorded demos: {}", e);
+            }
+            Vec::new()
+        }
+    };
+
+    let mut brains: Vec<B> = Vec::new();
+    for config in configs.iter() {
+        let reference = B::random(&config.game);
+        if brains.iter().any(|b| b.is_compatible(&reference)) {
+            continue;
+        }
+
+        if let Some(pretrained) = train::pretrained_brain(&config.game, &recorded) {
+            println!(
+                "Pretrained on {} demos, loss: {:.3}, accuracy: {:.2}",
+                pretrained.num_demos, pretrained.loss, pretrained.accuracy
+            );
+            brains.push(pretrained.brain);
+        }
+    }
+
+    brains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::es::Optimizer;
+
+    #[test]
+    fn random_topology_sends_migrants_to_every_target() {
+        // Migrants keep the board they were scored on, every stream gets its own width
+        let num_games = 2 * MIGRATION_NUM_MIGRANTS;
+        let stream = |idx: usize| -> Stream {
+            Stream::with_config(StreamConfig {
+                num_games,
+                optimizer: Optimizer::Genetic,
+                arena_size: 1,
+                game: GameConfig {
+                    grid_w: 20 + idx as i32,
+                    ..GameConfig::DEFAULT
+                },
+                ..StreamConfig::DEFAULT
+            })
+        };
+
+        for _ in 0..10 {
+            let mut pop = Population::with_streams((0..4).map(stream).collect());
+            pop.send_migrants(&[0, 1, 2, 3], MigrationTopology::Random);
+            for (idx, stream) in pop.streams.iter().enumerate() {
+                let num_migrants = stream
+                    .get_fittest_games(num_games)
+                    .iter()
+                    .filter(|g| g.config.grid_w != 20 + idx as i32)
+                    .count();
+                assert_eq!(num_migrants, MIGRATION_NUM_MIGRANTS);
+            }
         }
     }
 }
@@ -2,29 +2,70 @@
 //! Responsible for updating the population and viz
 //! Handles generations
 
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
 use macroquad::prelude::*;
 
+use crate::brain::Brain;
+use crate::curriculum::Curriculum;
+use crate::game::{Game, GameConfig};
+use crate::nn::Net;
 use crate::pop::Population;
+use crate::train::{self, Demo};
 use crate::viz::Viz;
+use crate::*;
 
-pub struct Simulation {
+pub struct Simulation<B: Brain = Net> {
     gen_count: usize,
-    pop: Population,
-    viz: Viz,
+    pop: Population<B>,
+    viz: Viz<B>,
+    curriculum: Curriculum,
+    /// Set while recording demos, evolution is paused
+    human_play: Option<HumanPlay<B>>,
+}
+
+struct HumanPlay<B: Brain> {
+    game: Game<B>,
+    dir: FourDirs,
+    demos: Vec<Demo>,
+    last_step_ts: Instant,
 }
 
-impl Simulation {
-    pub fn new() -> Self {
-        Self {
+impl<B: Brain> Simulation<B> {
+    /// Resumes the curriculum stage saved in `SEED_BRAINS_DIR` when there is one
+    /// Fails when a level file of the configs can't be loaded
+    pub fn new() -> io::Result<Self> {
+        let mut curriculum = Curriculum::new()?;
+        if let (Some(dir), Some(_)) = (SEED_BRAINS_DIR, curriculum.stage()) {
+            if let Err(e) = curriculum.load(Path::new(dir)) {
+                println!("Starting the curriculum from the first stage: {}", e);
+            }
+        }
+
+        let mut pop = Population::new()?;
+        if let Some(stage) = curriculum.current() {
+            pop.apply_curriculum_stage(stage);
+        }
+
+        Ok(Self {
             gen_count: 0,
-            pop: Population::new(),
+            pop,
             viz: Viz::new(),
-        }
+            curriculum,
+            human_play: None,
+        })
     }
 
     pub fn update(&mut self, is_viz_enabled: bool, is_slow_mode: bool) {
-        let games_alive = self.pop.update();
-        if games_alive <= 0 {
+        if self.human_play.is_some() {
+            self.update_human_play();
+            return;
+        }
+
+        let counts = self.pop.update();
+        if counts.alive == 0 {
             self.end_current_genration();
             self.start_new_generation();
         }
@@ -40,7 +81,117 @@ impl Simulation {
     }
 
     pub fn end_current_genration(&mut self) {
-        let stats = self.pop.get_gen_summary();
+        self.pop.validate_generation();
+        let mut stats = self.pop.get_gen_summary();
+        stats.curriculum_stage = self.curriculum.stage();
+        let validation_score = stats.validation.as_ref().map(|v| v.eval.mean);
         self.viz.reset(stats, self.gen_count);
+        self.update_curriculum(validation_score.unwrap_or(0.0));
+
+        if let Some(interval) = HALL_OF_FAME_SAVE_INTERVAL_GENS {
+            if (self.gen_count + 1) % interval == 0 {
+                self.save_hall_of_fame();
+            }
+        }
+    }
+
+    /// The next generation plays on a harder board when the score meets the stage threshold
+    fn update_curriculum(&mut self, score: f32) {
+        if !self.curriculum.update(score) {
+            return;
+        }
+
+        if let (Some(idx), Some(stage)) = (self.curriculum.stage(), self.curriculum.current()) {
+            println!("Curriculum stage {}: {:?}", idx, stage);
+            self.pop.apply_curriculum_stage(stage);
+        }
+    }
+
+    /// Starts recording demos from the arrow keys, or saves them and resumes evolution
+    /// Demos already in `PRETRAIN_DEMOS_FILE` are kept
+    pub fn toggle_human_play(&mut self) {
+        let path = Path::new(PRETRAIN_DEMOS_FILE);
+        if let Some(play) = self.human_play.take() {
+            match train::save_demos(&play.demos, path) {
+                Ok(_) => println!(
+                    "{} demos saved to {}",
+                    play.demos.len(),
+                    PRETRAIN_DEMOS_FILE
+                ),
+                Err(e) => println!("Failed to save the demos: {}", e),
+            }
+            return;
+        }
+
+        let demos = match train::load_demos(path) {
+            Ok(demos) => demos,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                println!("Failed to load the demos: {}", e);
+                return;
+            }
+        };
+        let config = match self.human_game_config() {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Failed to load the level: {}", e);
+                return;
+            }
+        };
+        let game = Game::with_config(&config);
+        self.human_play = Some(HumanPlay {
+            dir: game.dir,
+            game,
+            demos,
+            last_step_ts: Instant::now(),
+        });
+    }
+
+    /// Direction of the next steps of the recorded game
+    pub fn steer(&mut self, dir: FourDirs) {
+        if let Some(play) = self.human_play.as_mut() {
+            play.dir = dir;
+        }
+    }
+
+    /// One step every `HUMAN_PLAY_STEP_MILLIS`, a new game starts when the last one is over
+    fn update_human_play(&mut self) {
+        let Some(play) = self.human_play.as_mut() else {
+            return;
+        };
+
+        if play.last_step_ts.elapsed().as_millis() >= HUMAN_PLAY_STEP_MILLIS as u128 {
+            train::record_step(&mut play.game, play.dir, &mut play.demos);
+            play.last_step_ts = Instant::now();
+        }
+        if play.game.is_complete {
+            play.game = Game::with_config(&play.game.config);
+            play.dir = play.game.dir;
+        }
+
+        self.viz.draw_human_game(&play.game, play.demos.len());
+    }
+
+    /// Default game on the board of the current curriculum stage
+    fn human_game_config(&self) -> io::Result<GameConfig> {
+        let mut config = GameConfig::default();
+        config.load_level()?;
+        if let Some(stage) = self.curriculum.current() {
+            stage.apply(&mut config);
+        }
+        Ok(config)
+    }
+
+    /// The hall of fame along with the curriculum stage
+    pub fn save_hall_of_fame(&self) {
+        let dir = Path::new(HALL_OF_FAME_DIR);
+        let result = self
+            .pop
+            .save_hall_of_fame(dir)
+            .and_then(|_| self.curriculum.save(dir));
+        match result {
+            Ok(_) => println!("Hall of fame saved to {}", HALL_OF_FAME_DIR),
+            Err(e) => println!("Failed to save the hall of fame: {}", e),
+        }
     }
 }
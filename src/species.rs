@@ -0,0 +1,245 @@
+//! Species
+//! Groups the games of a stream by the weight-space distance of their brains
+//! Used for fitness sharing and per-species offspring quotas
+
+use rand::Rng;
+
+use crate::brain::Brain;
+use crate::game::Game;
+use crate::nn::Net;
+use crate::*;
+
+pub struct Species<B: Brain = Net> {
+    pub id: usize,
+    pub representative: B,
+    pub members: Vec<usize>,
+}
+
+pub struct SpeciesSet<B: Brain = Net> {
+    species: Vec<Species<B>>,
+    next_id: usize,
+    threshold: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpeciesSummary {
+    pub id: usize,
+    pub size: usize,
+    pub max_score: usize,
+    pub mean_fitness: f32,
+}
+
+impl<B: Brain> SpeciesSet<B> {
+    pub fn new() -> Self {
+        Self {
+            species: Vec::new(),
+            next_id: 0,
+            threshold: SPECIES_DISTANCE_THRESHOLD,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Species<B>> {
+        self.species.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.species.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.species.is_empty()
+    }
+
+    /// Assigns every game to the first species whose representative is close enough,
+    /// new species are created for games that don't fit anywhere
+    pub fn speciate(&mut self, games: &[Game<B>]) {
+        let threshold = self.threshold;
+        self.species.iter_mut().for_each(|s| s.members.clear());
+
+        for (idx, game) in games.iter().enumerate() {
+            let species = self
+                .species
+                .iter_mut()
+                .find(|s| s.representative.distance(&game.brain) < threshold);
+
+            match species {
+                Some(species) => species.members.push(idx),
+                None => {
+                    self.species.push(Species {
+                        id: self.next_id,
+                        representative: game.brain.clone(),
+                        members: vec![idx],
+                    });
+                    self.next_id += 1;
+                }
+            }
+        }
+
+        // Drop extinct species and pick new representatives
+        let mut rng = rand::thread_rng();
+        self.species.retain(|s| !s.members.is_empty());
+        for species in self.species.iter_mut() {
+            let idx = species.members[rng.gen_range(0..species.members.len())];
+            species.representative = games[idx].brain.clone();
+        }
+    }
+
+    /// Nudges the distance threshold towards `SPECIES_TARGET_COUNT` species
+    /// Call once per generation, before speciating the new games
+    pub fn adjust_threshold(&mut self) {
+        if self.species.len() > SPECIES_TARGET_COUNT {
+            self.threshold += SPECIES_THRESHOLD_STEP;
+        } else if self.species.len() < SPECIES_TARGET_COUNT {
+            self.threshold = (self.threshold - SPECIES_THRESHOLD_STEP).max(SPECIES_THRESHOLD_STEP);
+        }
+    }
+
+    /// Score of every game divided by the size of its species
+    pub fn shared_scores(&self, scores: &[f32]) -> Vec<f32> {
+        let mut shared = vec![0.0; scores.len()];
+        for species in self.species.iter() {
+            let size = species.members.len() as f32;
+            for &idx in species.members.iter() {
+                shared[idx] = scores[idx] / size;
+            }
+        }
+
+        shared
+    }
+
+    /// Splits `total` offspring between species, proportional to their summed shared scores
+    pub fn offspring_quotas(&self, shared_scores: &[f32], total: usize) -> Vec<usize> {
+        let mut weights: Vec<f32> = self
+            .species
+            .iter()
+            .map(|s| s.members.iter().map(|&idx| shared_scores[idx]).sum())
+            .collect();
+
+        let weights_sum: f32 = weights.iter().sum();
+        if !weights_sum.is_finite() || weights_sum <= 0.0 {
+            weights = self
+                .species
+                .iter()
+                .map(|s| s.members.len() as f32)
+                .collect();
+        }
+        let weights_sum: f32 = weights.iter().sum();
+        if weights_sum <= 0.0 {
+            return vec![0; self.species.len()];
+        }
+
+        // Largest remainder, so the quotas always add up to total
+        let exact: Vec<f32> = weights
+            .iter()
+            .map(|w| w / weights_sum * total as f32)
+            .collect();
+        let mut quotas: Vec<usize> = exact.iter().map(|q| q.floor() as usize).collect();
+        let mut remainders: Vec<(usize, f32)> = exact
+            .iter()
+            .enumerate()
+            .map(|(i, q)| (i, q - q.floor()))
+            .collect();
+        remainders.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let assigned: usize = quotas.iter().sum();
+        for (i, _) in remainders.iter().take(total.saturating_sub(assigned)) {
+            quotas[*i] += 1;
+        }
+
+        quotas
+    }
+
+    pub fn summary(&self, games: &[Game<B>]) -> Vec<SpeciesSummary> {
+        let mut summaries = Vec::new();
+        for species in self.species.iter() {
+            let mut max_score = 0;
+            let mut total_fitness = 0.0;
+            for &idx in species.members.iter() {
+                max_score = max_score.max(games[idx].score());
+                total_fitness += games[idx].finite_fitness();
+            }
+
+            summaries.push(SpeciesSummary {
+                id: species.id,
+                size: species.members.len(),
+                max_score,
+                mean_fitness: total_fitness / species.members.len() as f32,
+            });
+        }
+
+        summaries.sort_by_key(|s| std::cmp::Reverse(s.max_score));
+        summaries
+    }
+}
+
+impl<B: Brain> Default for SpeciesSet<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn species_set(member_groups: &[&[usize]]) -> SpeciesSet {
+        let mut set = SpeciesSet::new();
+        for members in member_groups.iter() {
+            set.species.push(Species {
+                id: set.next_id,
+                representative: Net::with_shape(&[2, 2]),
+                members: members.to_vec(),
+            });
+            set.next_id += 1;
+        }
+        set
+    }
+
+    #[test]
+    fn offspring_quotas_use_the_largest_remainders() {
+        let set = species_set(&[&[0, 1], &[2], &[3, 4, 5]]);
+        // Summed shares 0.5, 0.3 and 0.2, exact quotas 3.5, 2.1 and 1.4
+        let shared = [0.25, 0.25, 0.3, 0.1, 0.05, 0.05];
+        assert_eq!(set.offspring_quotas(&shared, 7), vec![4, 2, 1]);
+        assert_eq!(set.offspring_quotas(&shared, 10), vec![5, 3, 2]);
+        assert_eq!(set.offspring_quotas(&shared, 0), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn offspring_quotas_fall_back_to_species_sizes() {
+        let set = species_set(&[&[0, 1], &[2], &[3, 4, 5]]);
+        let quotas = set.offspring_quotas(&[0.0; 6], 12);
+        assert_eq!(quotas, vec![4, 2, 6]);
+        let quotas = set.offspring_quotas(&[f32::MAX; 6], 5);
+        assert_eq!(quotas.iter().sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn adjust_threshold_moves_towards_the_target_count() {
+        let mut set = species_set(&[]);
+        set.adjust_threshold();
+        assert_eq!(
+            set.threshold,
+            SPECIES_DISTANCE_THRESHOLD - SPECIES_THRESHOLD_STEP
+        );
+        for _ in 0..1000 {
+            set.adjust_threshold();
+        }
+        assert_eq!(set.threshold, SPECIES_THRESHOLD_STEP);
+
+        let groups: Vec<Vec<usize>> = (0..=SPECIES_TARGET_COUNT).map(|i| vec![i]).collect();
+        let groups: Vec<&[usize]> = groups.iter().map(|g| g.as_slice()).collect();
+        let mut set = species_set(&groups);
+        set.adjust_threshold();
+        assert_eq!(
+            set.threshold,
+            SPECIES_DISTANCE_THRESHOLD + SPECIES_THRESHOLD_STEP
+        );
+
+        let groups: Vec<Vec<usize>> = (0..SPECIES_TARGET_COUNT).map(|i| vec![i]).collect();
+        let groups: Vec<&[usize]> = groups.iter().map(|g| g.as_slice()).collect();
+        let mut set = species_set(&groups);
+        set.adjust_threshold();
+        assert_eq!(set.threshold, SPECIES_DISTANCE_THRESHOLD);
+    }
+}
@@ -0,0 +1,214 @@
+//! Start
+//! Where and how a snake starts a game
+//! Randomized starts keep brains from overfitting to the center spawn
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StartPosition {
+    Center,
+    /// Any cell that isn't a wall
+    Random,
+}
+
+/// Fixed start, the body is head first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StartState {
+    pub body: &'static [(i32, i32)],
+    pub dir: FourDirs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StartConfig {
+    pub position: StartPosition,
+    /// The body unfolds randomly behind the head
+    pub min_length: usize,
+    pub max_length: usize,
+    /// None for a random direction
+    pub dir: Option<FourDirs>,
+    /// One of them is picked at random when set, the other options are ignored
+    /// unless none of them fit the board
+    pub presets: &'static [StartState],
+}
+
+impl StartState {
+    /// A non-empty body of distinct open cells, each next to the one before
+    fn fits(&self, is_open: impl Fn(Point) -> bool) -> bool {
+        let body: Vec<Point> = self.body.iter().map(|&pt| pt.into()).collect();
+        let is_connected = body
+            .windows(2)
+            .all(|w| (w[0].x - w[1].x).abs() + (w[0].y - w[1].y).abs() == 1);
+        let is_distinct = body
+            .iter()
+            .enumerate()
+            .all(|(idx, pt)| !body[..idx].contains(pt));
+
+        !body.is_empty() && body.iter().all(|&pt| is_open(pt)) && is_connected && is_distinct
+    }
+}
+
+impl StartConfig {
+    pub const DEFAULT: Self = Self {
+        position: START_POSITION,
+        min_length: START_MIN_LENGTH,
+        max_length: START_MAX_LENGTH,
+        dir: START_DIR,
+        presets: START_PRESETS,
+    };
+
+    /// A single cell in the center facing a random direction
+    pub const CLASSIC: Self = Self {
+        position: StartPosition::Center,
+        min_length: 1,
+        max_length: 1,
+        dir: None,
+        presets: &[],
+    };
+
+    /// Body, head first, and direction of a new snake
+    /// `is_free` tells the cells inside the border the body can take
+    /// Presets that don't fit the board are skipped, the random start is used when none fit
+    pub fn generate(
+        &self,
+        grid_w: i32,
+        grid_h: i32,
+        rng: &mut impl Rng,
+        is_free: impl Fn(Point) -> bool,
+    ) -> (Vec<Point>, FourDirs) {
+        let is_open =
+            |pt: Point| pt.x > 0 && pt.x < grid_w && pt.y > 0 && pt.y < grid_h && is_free(pt);
+        let presets: Vec<&StartState> = self
+            .presets
+            .iter()
+            .filter(|preset| preset.fits(is_open))
+            .collect();
+        if let Some(preset) = presets.choose(rng) {
+            return (
+                preset.body.iter().map(|&pt| pt.into()).collect(),
+                preset.dir,
+            );
+        }
+
+        let center = Point::new(grid_w / 2, grid_h / 2);
+        let head = match self.position {
+            StartPosition::Center => center,
+            StartPosition::Random => (0..100)
+                .map(|_| Point::rand(rng, grid_w, grid_h))
+                .find(|&pt| is_open(pt))
+                .unwrap_or(center),
+        };
+        // A random direction avoids facing a wall when it can
+        let dir = self.dir.unwrap_or_else(|| {
+            let mut dir = FourDirs::get_rand_dir(rng);
+            for _ in 0..8 {
+                let (dx, dy) = dir.value();
+                if is_open(Point::new(head.x + dx, head.y + dy)) {
+                    break;
+                }
+                dir = FourDirs::get_rand_dir(rng);
+            }
+            dir
+        });
+        let length = rng.gen_range(self.min_length..=self.max_length.max(self.min_length));
+
+        // The neck is behind the head and the cell ahead is kept free, the first move is safe
+        let (dx, dy) = dir.value();
+        let ahead = Point::new(head.x + dx, head.y + dy);
+        let mut body = vec![head];
+        let mut next = Some(Point::new(head.x - dx, head.y - dy));
+        while body.len() < length {
+            let Some(pt) = next.filter(|&pt| is_open(pt) && pt != ahead && !body.contains(&pt))
+            else {
+                break;
+            };
+
+            body.push(pt);
+            let options: Vec<Point> = FourDirs::get_all_dirs()
+                .iter()
+                .map(|d| Point::new(pt.x + d.0, pt.y + d.1))
+                .filter(|&p| is_open(p) && p != ahead && !body.contains(&p))
+                .collect();
+            next = options.choose(rng).copied();
+        }
+
+        (body, dir)
+    }
+}
+
+impl Default for StartConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn with_presets(presets: &'static [StartState]) -> StartConfig {
+        StartConfig {
+            presets,
+            ..StartConfig::CLASSIC
+        }
+    }
+
+    #[test]
+    fn fitting_preset_is_used() {
+        let config = with_presets(&[StartState {
+            body: &[(5, 5), (4, 5), (4, 6)],
+            dir: FourDirs::Right,
+        }]);
+        let (body, dir) = config.generate(10, 10, &mut StdRng::seed_from_u64(0), |_| true);
+        assert_eq!(
+            body,
+            vec![Point::new(5, 5), Point::new(4, 5), Point::new(4, 6)]
+        );
+        assert_eq!(dir, FourDirs::Right);
+    }
+
+    #[test]
+    fn presets_that_dont_fit_fall_back_to_the_random_start() {
+        let config = with_presets(&[
+            // Empty
+            StartState {
+                body: &[],
+                dir: FourDirs::Right,
+            },
+            // Outside the grid and on the border
+            StartState {
+                body: &[(12, 5)],
+                dir: FourDirs::Right,
+            },
+            StartState {
+                body: &[(0, 5)],
+                dir: FourDirs::Right,
+            },
+            // Cells that aren't next to each other
+            StartState {
+                body: &[(5, 5), (3, 5)],
+                dir: FourDirs::Right,
+            },
+            // The same cell twice
+            StartState {
+                body: &[(5, 5), (4, 5), (5, 5)],
+                dir: FourDirs::Right,
+            },
+            // On a level wall
+            StartState {
+                body: &[(2, 2)],
+                dir: FourDirs::Right,
+            },
+        ]);
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (body, _) = config.generate(10, 10, &mut rng, |pt| pt != Point::new(2, 2));
+            assert_eq!(body, vec![Point::new(5, 5)]);
+        }
+    }
+}
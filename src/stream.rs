@@ -1,118 +1,530 @@
 //! Stream
 //! Island of neuro-evolving agents
 
-use std::time::Instant;
+use std::fs;
+use std::io;
+use std::path::Path;
 
 use rand::distributions::{Distribution, WeightedIndex};
-
-use crate::game::Game;
-use crate::nn::Net;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::arena;
+use crate::brain::Brain;
+use crate::curriculum::CurriculumStage;
+use crate::es::{Optimizer, Strategy};
+use crate::eval;
+use crate::game::{Game, GameConfig, GameCounts};
+use crate::hall_of_fame;
+use crate::migration::ReplacementPolicy;
+use crate::nn::{BatchNet, Net};
+use crate::novelty::NoveltyArchive;
+use crate::pareto;
+use crate::species::{SpeciesSet, SpeciesSummary};
 use crate::*;
 
-pub struct Stream {
-    games: Vec<Game>,
-    max_score: usize,
-    max_score_ts: Instant,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionMode {
+    Fitness,
+    Novelty,
+    /// Weighted by `NOVELTY_WEIGHT`
+    Combined,
+    /// Pareto ranking over `PARETO_OBJECTIVES`
+    Pareto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StagnationResponse {
+    /// Receive migrants from the other streams
+    Migration,
+    /// Mutate with `STAGNATION_MUTATION_BOOST` times the rate for a few generations
+    MutationBoost,
+    /// Replace `STAGNATION_RESTART_PERCENT` of the stream with random snakes
+    PartialRestart,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeedFill {
+    /// Mutated copies of the seed brains
+    Mutated,
+    /// Random brains
+    Random,
+}
+
+/// Hyperparameters of a single stream, so streams of one run can explore different settings
+#[derive(Clone, Debug)]
+pub struct StreamConfig {
+    pub num_games: usize,
+    pub mutation_rate: f32,
+    pub mutation_variation: f32,
+    pub num_retained: f32,
+    pub num_children: f32,
+    pub num_random: f32,
+    pub num_retained_mutated: f32,
+    pub optimizer: Optimizer,
+    /// Snakes sharing a board, 1 to play alone
+    pub arena_size: usize,
+    pub game: GameConfig,
+}
+
+/// Brains of a stream packed for batched inference
+enum Batch {
+    /// Packed again at the next batched step
+    Stale,
+    /// The brains can't be packed, eg. recurrent nets or NEAT genomes
+    Unsupported,
+    Packed(BatchNet),
 }
 
-impl Stream {
+pub struct Stream<B: Brain = Net> {
+    config: StreamConfig,
+    games: Vec<Game<B>>,
+    species: SpeciesSet<B>,
+    archive: NoveltyArchive,
+    best_fitness: f32,
+    best_mean_fitness: f32,
+    stagnant_gens: usize,
+    mutation_boost_gens: usize,
+    is_restart_pending: bool,
+    /// Packed brains of the games, stale whenever the games change
+    batch: Batch,
+    /// Alive games and their inputs, reused by every batched step
+    batch_alive: Vec<usize>,
+    batch_inputs: Vec<f32>,
+    /// Created at the first reset when an evolution strategy is the optimizer
+    strategy: Option<Strategy>,
+}
+
+impl StreamConfig {
+    pub const DEFAULT: Self = Self {
+        num_games: NUM_GAMES_PER_STREAM,
+        mutation_rate: BRAIN_MUTATION_RATE,
+        mutation_variation: BRAIN_MUTATION_VARIATION,
+        num_retained: POP_NUM_RETAINED,
+        num_children: POP_NUM_CHILDREN,
+        num_random: POP_NUM_RANDOM,
+        num_retained_mutated: POP_NUM_RETAINED_MUTATED,
+        optimizer: STREAM_OPTIMIZER,
+        arena_size: ARENA_NUM_SNAKES,
+        game: GameConfig::DEFAULT,
+    };
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl<B: Brain> Stream<B> {
     pub fn new() -> Self {
+        Self::with_config(StreamConfig::default())
+    }
+
+    pub fn with_config(config: StreamConfig) -> Self {
+        let mut games = Vec::new();
+        for _ in 0..config.num_games {
+            games.push(Game::with_config(&config.game));
+        }
+
+        Self::with_games(config, games)
+    }
+
+    /// Starts from the given brains, the rest of the stream is filled depending on `fill`
+    pub fn with_brains(config: StreamConfig, brains: &[B], fill: SeedFill) -> Self {
         let mut games = Vec::new();
-        for _ in 0..NUM_GAMES_PER_STREAM {
-            games.push(Game::new());
+        for idx in 0..config.num_games {
+            let mut game = Game::with_config(&config.game);
+            if idx < brains.len() {
+                game.brain = brains[idx].clone();
+            } else if fill == SeedFill::Mutated && !brains.is_empty() {
+                game.brain = brains[idx % brains.len()].clone();
+                game.brain
+                    .mutate(config.mutation_rate, config.mutation_variation);
+            }
+            games.push(game);
+        }
+
+        Self::with_games(config, games)
+    }
+
+    /// Starts from the brains saved in `dir`, eg. a hall of fame
+    /// Files that don't parse and brains that can't play with this stream's game config are skipped
+    pub fn from_dir(config: StreamConfig, dir: &Path, fill: SeedFill) -> io::Result<Self> {
+        let brains: Vec<B> = hall_of_fame::load_brains(dir)?;
+        Self::from_saved(config, &brains, fill).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("No brains for this stream in {}", dir.display()),
+            )
+        })
+    }
+
+    /// Starts from the saved brains that can play with this stream's game config
+    /// None when none of them can
+    pub fn from_saved(config: StreamConfig, brains: &[B], fill: SeedFill) -> Option<Self> {
+        let reference = B::random(&config.game);
+        let brains: Vec<B> = brains
+            .iter()
+            .filter(|b| b.is_compatible(&reference))
+            .cloned()
+            .collect();
+        if brains.is_empty() {
+            return None;
         }
 
-        Self {
+        Some(Self::with_brains(config, &brains, fill))
+    }
+
+    fn with_games(config: StreamConfig, games: Vec<Game<B>>) -> Self {
+        let mut stream = Self {
+            config,
             games,
-            max_score: 0,
-            max_score_ts: Instant::now(),
+            species: SpeciesSet::new(),
+            archive: NoveltyArchive::new(),
+            best_fitness: 0.0,
+            best_mean_fitness: 0.0,
+            stagnant_gens: 0,
+            mutation_boost_gens: 0,
+            is_restart_pending: false,
+            batch: Batch::Stale,
+            batch_alive: Vec::new(),
+            batch_inputs: Vec::new(),
+            strategy: None,
+        };
+        stream.seat_arenas();
+        if SPECIATION_ENABLED {
+            stream.species.speciate(&stream.games);
         }
+        stream
     }
 
-    pub fn update(&mut self) -> usize {
-        let mut games_alive = NUM_GAMES_PER_STREAM;
+    pub fn update(&mut self) -> GameCounts {
+        if self.config.arena_size > 1 {
+            self.update_arenas();
+        } else if BATCH_INFERENCE_ENABLED {
+            self.update_batched();
+        } else {
+            self.update_sequential();
+        }
+
+        self.counts()
+    }
+
+    /// Steps every arena, the snakes of an arena are next to each other in the games
+    pub fn update_arenas(&mut self) {
+        for snakes in self.games.chunks_mut(self.config.arena_size) {
+            arena::update(snakes);
+        }
+    }
+
+    /// Shuffles the games onto shared boards when `arena_size` is more than 1
+    /// so the fittest snakes don't always meet each other
+    fn seat_arenas(&mut self) {
+        if self.config.arena_size <= 1 {
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        self.games.shuffle(&mut rng);
+        for snakes in self.games.chunks_mut(self.config.arena_size) {
+            let brains: Vec<B> = snakes.iter().map(|g| g.brain.clone()).collect();
+            let seated =
+                arena::new_arena(&self.config.game, &brains, eval::training_seed(&mut rng));
+            for (snake, seated) in snakes.iter_mut().zip(seated) {
+                *snake = seated;
+            }
+        }
+    }
 
+    /// Steps every game with its own forward pass
+    pub fn update_sequential(&mut self) {
         for g in self.games.iter_mut() {
             g.update();
+        }
+    }
 
-            let score = g.score();
-            if score > self.max_score {
-                self.max_score = score;
-                self.max_score_ts = Instant::now();
+    /// Steps every alive game with a single forward pass over the packed brains
+    /// Falls back to `update_sequential` when the brains can't be packed
+    pub fn update_batched(&mut self) {
+        if matches!(self.batch, Batch::Stale) {
+            let brains: Vec<&B> = self.games.iter().map(|g| &g.brain).collect();
+            self.batch = match B::batch(&brains) {
+                Some(batch) => Batch::Packed(batch),
+                None => Batch::Unsupported,
+            };
+        }
+        let Batch::Packed(batch) = &mut self.batch else {
+            self.update_sequential();
+            return;
+        };
+
+        self.batch_alive.clear();
+        self.batch_inputs.clear();
+        for (idx, game) in self.games.iter().enumerate() {
+            if !game.is_complete {
+                self.batch_alive.push(idx);
+                self.batch_inputs
+                    .extend(game.observe().iter().map(|&v| v as f32));
             }
+        }
+        if self.batch_alive.is_empty() {
+            return;
+        }
 
-            if g.is_complete {
-                games_alive -= 1;
+        let num_outputs = batch.num_outputs();
+        let outputs = batch.predict(&self.batch_alive, &self.batch_inputs);
+        let mut nn_out = vec![0.0; num_outputs];
+        for (&idx, out) in self
+            .batch_alive
+            .iter()
+            .zip(outputs.chunks_exact(num_outputs))
+        {
+            for (value, &v) in nn_out.iter_mut().zip(out.iter()) {
+                *value = v as f64;
             }
+            self.games[idx].update_with_output(&nn_out);
         }
+    }
 
-        NUM_GAMES_PER_STREAM - games_alive
+    pub fn counts(&self) -> GameCounts {
+        GameCounts::from_games(&self.games)
     }
 
-    pub fn is_local_maximum(&self) -> bool {
-        self.max_score_ts.elapsed().as_secs_f32() > STREAM_LOCAL_MAX_WAIT_SECS
+    /// True once neither the best nor the mean fitness improved
+    /// for `STREAM_STAGNATION_GENS` generations
+    pub fn is_stagnant(&self) -> bool {
+        self.stagnant_gens >= STREAM_STAGNATION_GENS
     }
 
-    pub fn inject(&mut self, net: &Net) {
-        let new_game = Game::with_brain(net);
-        let num_games = (NUM_GAMES_PER_STREAM as f32 * STREAM_REJUVENATION_PERCENT) as usize;
+    /// Compares the generation that just completed against the best seen so far
+    /// Overflowed fitness counts as the highest like in selection,
+    /// divided rather than multiplied so values near f32::MAX still compare
+    pub fn update_stagnation(&mut self) {
+        let fitness = self.finite_fitness();
+        if fitness.is_empty() {
+            return;
+        }
 
-        self.games.drain(0..num_games);
-        for _ in 0..num_games {
-            self.games.push(new_game.clone());
+        let gen_best = fitness.iter().cloned().fold(0.0, f32::max);
+        let gen_mean = fitness
+            .iter()
+            .map(|f| f / fitness.len() as f32)
+            .sum::<f32>()
+            .min(f32::MAX);
+        let min_improvement = 1.0 + STREAM_STAGNATION_MIN_IMPROVEMENT;
+
+        let mut is_improved = false;
+        if gen_best / min_improvement > self.best_fitness {
+            self.best_fitness = gen_best;
+            is_improved = true;
+        }
+        if gen_mean / min_improvement > self.best_mean_fitness {
+            self.best_mean_fitness = gen_mean;
+            is_improved = true;
         }
 
-        self.max_score = 0;
-        self.max_score_ts = Instant::now();
+        if is_improved {
+            self.stagnant_gens = 0;
+        } else {
+            self.stagnant_gens += 1;
+        }
     }
 
-    pub fn get_stream_summary(&self) -> (usize, Option<Net>) {
-        let mut max_score = 0;
-        let mut best_net = None;
+    /// Applies the stream level `STREAM_STAGNATION_RESPONSES`
+    /// Migration is handled by the population
+    pub fn handle_stagnation(&mut self) {
+        self.respond_to_stagnation(STREAM_STAGNATION_RESPONSES);
+    }
 
-        for g in self.games.iter() {
-            let score = g.score();
-            if score > max_score {
-                max_score = score;
-                best_net = Some(g.brain.clone());
+    fn respond_to_stagnation(&mut self, responses: &[StagnationResponse]) {
+        if !self.is_stagnant() {
+            return;
+        }
+
+        for response in responses.iter() {
+            match response {
+                StagnationResponse::Migration => {}
+                StagnationResponse::MutationBoost => {
+                    self.mutation_boost_gens = STAGNATION_MUTATION_BOOST_GENS;
+                }
+                StagnationResponse::PartialRestart => self.is_restart_pending = true,
             }
         }
+        self.stagnant_gens = 0;
+    }
+
+    /// Copies of the fittest games, best first
+    pub fn get_fittest_games(&self, num_games: usize) -> Vec<Game<B>> {
+        let mut games_sorted = self.games.clone();
+        games_sorted.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        games_sorted.truncate(num_games);
+
+        games_sorted
+    }
+
+    /// Replaces games with the migrants, they compete in the next selection
+    /// with the fitness they earned in their own stream
+    /// Migrants keep the config of their own board so their state and score stay consistent,
+    /// their brains play on this stream's board from the next generation on
+    pub fn inject(&mut self, migrants: &[Game<B>], policy: ReplacementPolicy) {
+        let mut rng = rand::thread_rng();
+        let mut targets: Vec<usize> = (0..self.games.len()).collect();
+        match policy {
+            ReplacementPolicy::Worst => targets.sort_by(|&a, &b| {
+                self.games[a]
+                    .partial_cmp(&self.games[b])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            ReplacementPolicy::Random => targets.shuffle(&mut rng),
+        }
+
+        // Brains that can't see this stream's inputs or lack its memory can't migrate here
+        let reference = B::random(&self.config.game);
+        let migrants = migrants
+            .iter()
+            .filter(|m| m.brain.is_compatible(&reference));
+        for (&idx, migrant) in targets.iter().zip(migrants) {
+            let mut migrant = migrant.clone();
+            migrant.set_opponents(Vec::new());
+            self.games[idx] = migrant;
+        }
+        self.batch = Batch::Stale;
+        if SPECIATION_ENABLED {
+            self.species.speciate(&self.games);
+        }
+    }
+
+    /// Next generations play on the stage's board, games that haven't started are restarted
+    pub fn apply_curriculum_stage(&mut self, stage: &CurriculumStage) {
+        stage.apply(&mut self.config.game);
+        for game in self.games.iter_mut().filter(|g| g.num_steps() == 0) {
+            let mut new_game = Game::with_config(&self.config.game);
+            std::mem::swap(&mut new_game.brain, &mut game.brain);
+            *game = new_game;
+        }
+        if self.config.arena_size > 1 && self.games.iter().all(|g| g.num_steps() == 0) {
+            self.seat_arenas();
+            if SPECIATION_ENABLED {
+                self.species.speciate(&self.games);
+            }
+        }
+    }
+
+    /// Writes the evolution strategy state, nothing for the genetic algorithm
+    pub fn save_strategy(&self, path: &Path) -> io::Result<()> {
+        match &self.strategy {
+            Some(strategy) => fs::write(path, strategy.to_text()),
+            None => Ok(()),
+        }
+    }
+
+    /// Resumes the evolution strategy saved at `path`, the games are resampled from it
+    /// Ignored with the genetic algorithm
+    pub fn load_strategy(&mut self, path: &Path) -> io::Result<()> {
+        if self.config.optimizer == Optimizer::Genetic {
+            return Ok(());
+        }
+        let Some(template) = self.games.first().map(|g| g.brain.clone()) else {
+            return Ok(());
+        };
+
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let strategy = Strategy::from_text(&fs::read_to_string(path)?)?;
+        if strategy.optimizer() != self.config.optimizer {
+            return Err(invalid(format!(
+                "Strategy saved for {:?}, the stream uses {:?}",
+                strategy.optimizer(),
+                self.config.optimizer
+            )));
+        }
+        if template.params().map(|p| p.len()) != Some(strategy.num_params()) {
+            return Err(invalid(format!(
+                "Strategy in {} doesn't fit the brains",
+                path.display()
+            )));
+        }
+
+        let samples = strategy.ask(self.games.len());
+        for (game, params) in self.games.iter_mut().zip(samples) {
+            if let Some(brain) = template.with_params(&params) {
+                game.brain = brain;
+            }
+        }
+        self.strategy = Some(strategy);
+        self.batch = Batch::Stale;
+        if SPECIATION_ENABLED {
+            self.species.speciate(&self.games);
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+
+    pub fn get_max_score(&self) -> usize {
+        self.games.iter().map(|g| g.score()).max().unwrap_or(0)
+    }
+
+    pub fn get_species_summary(&self) -> Vec<SpeciesSummary> {
+        if !SPECIATION_ENABLED {
+            return Vec::new();
+        }
+
+        self.species.summary(&self.games)
+    }
+
+    /// Objective values of the non-dominated games, only in pareto selection mode
+    pub fn get_pareto_front(&self) -> Vec<Vec<f32>> {
+        if SELECTION_MODE != SelectionMode::Pareto {
+            return Vec::new();
+        }
 
-        (max_score, best_net)
+        let points: Vec<Vec<f32>> = self.games.iter().map(pareto::objectives).collect();
+        pareto::pareto_front(&points)
     }
 
-    pub fn reset(&mut self) -> Net {
+    pub fn reset(&mut self) {
+        if self.config.optimizer != Optimizer::Genetic && self.reset_with_strategy() {
+            return;
+        }
+
         let mut rng = rand::thread_rng();
-        let gene_pool = self.generate_gene_pool();
+        let mode_scores = self.mode_scores();
+        let scores = self.share_scores(mode_scores.clone());
+        let gene_pool = self.generate_gene_pool(&scores);
         let mut new_games = Vec::new();
 
         // Population Distribution
-        let num_retained = NUM_GAMES_PER_STREAM as f32 * POP_NUM_RETAINED;
-        let num_children = NUM_GAMES_PER_STREAM as f32 * POP_NUM_CHILDREN;
-        let num_random = NUM_GAMES_PER_STREAM as f32 * POP_NUM_RANDOM;
-        let mut num_retained_mutated = NUM_GAMES_PER_STREAM as f32 * POP_NUM_RETAINED_MUTATED;
-
-        // Retained no mutation
-        let mut games_sorted = self.games.clone();
-        games_sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        games_sorted.reverse();
-        for i in 0..num_retained as usize {
-            let old_brain = games_sorted[i].brain.clone();
-            let mut new_game = Game::new();
+        let num_games = self.config.num_games as f32;
+        let num_retained = num_games * self.config.num_retained;
+        let num_children = num_games * self.config.num_children;
+        let num_random = num_games * self.config.num_random;
+        let mut num_retained_mutated = num_games * self.config.num_retained_mutated;
+
+        // Retained no mutation, by raw fitness so shared scores don't drop a species champion
+        let elites = self.sort_by_scores(&self.finite_fitness());
+        for game in elites.iter().take(num_retained as usize) {
+            let old_brain = game.brain.clone();
+            let mut new_game = self.new_game();
             new_game.brain = old_brain;
 
             new_games.push(new_game);
         }
 
         // Children
-        if let Some(pool) = gene_pool {
+        if SPECIATION_ENABLED && !self.species.is_empty() {
+            new_games.extend(self.generate_species_children(&scores, num_children as usize));
+        } else if let Some(pool) = gene_pool {
             for _ in 0..num_children as i32 {
-                let rand_parent_1 = self.games[pool.sample(&mut rng)].clone();
-                let rand_parent_2 = self.games[pool.sample(&mut rng)].clone();
-                let mut new_brain = rand_parent_1.brain.merge(&rand_parent_2.brain);
-                new_brain.mutate();
+                let rand_parent_1 = &self.games[pool.sample(&mut rng)];
+                let rand_parent_2 = &self.games[pool.sample(&mut rng)];
+                let mut new_brain = crossover(rand_parent_1, rand_parent_2);
+                self.mutate(&mut new_brain);
 
-                let new_game = Game::with_brain(&new_brain);
+                let mut new_game = self.new_game();
+                new_game.brain = new_brain;
                 new_games.push(new_game);
             }
         } else {
@@ -120,11 +532,12 @@ impl Stream {
             num_retained_mutated += num_children;
         }
 
-        // Retained with mutation
-        for i in 0..num_retained_mutated as usize {
-            let mut old_brain = games_sorted[i].brain.clone();
-            let mut new_game = Game::new();
-            old_brain.mutate();
+        // Retained with mutation, ranked by the selection mode before fitness sharing
+        let games_sorted = self.sort_by_scores(&mode_scores);
+        for game in games_sorted.iter().take(num_retained_mutated as usize) {
+            let mut old_brain = game.brain.clone();
+            let mut new_game = self.new_game();
+            self.mutate(&mut old_brain);
             new_game.brain = old_brain;
 
             new_games.push(new_game);
@@ -132,31 +545,390 @@ impl Stream {
 
         // Full random
         for _ in 0..num_random as i32 {
-            new_games.push(Game::new());
+            new_games.push(self.new_game());
         }
 
+        // Partial restart, keeps the retained snakes at the front
+        if self.is_restart_pending {
+            new_games.truncate(num_kept_after_restart(
+                new_games.len(),
+                num_retained as usize,
+            ));
+            self.is_restart_pending = false;
+        }
+
+        // Rounding in the distribution, keep the stream size exact
+        new_games.truncate(self.config.num_games);
+        while new_games.len() < self.config.num_games {
+            new_games.push(self.new_game());
+        }
+        self.mutation_boost_gens = self.mutation_boost_gens.saturating_sub(1);
+
         self.games = new_games;
-        games_sorted[0].brain.clone()
+        self.batch = Batch::Stale;
+        self.seat_arenas();
+        if SPECIATION_ENABLED {
+            self.species.adjust_threshold();
+            self.species.speciate(&self.games);
+        }
     }
 
-    fn generate_gene_pool(&self) -> Option<WeightedIndex<f32>> {
-        let mut max_fitness = 0.0;
-        let mut weights = Vec::new();
+    /// Next generation sampled by the evolution strategy
+    /// False when the brains have no flat params, the genetic algorithm is used instead
+    fn reset_with_strategy(&mut self) -> bool {
+        let Some(samples) = self
+            .games
+            .iter()
+            .map(|g| g.brain.params())
+            .collect::<Option<Vec<Vec<f64>>>>()
+        else {
+            return false;
+        };
+
+        if self.games.is_empty() {
+            return false;
+        }
 
-        for game in self.games.iter() {
-            let fitness = game.fitness();
-            if fitness > max_fitness {
-                max_fitness = fitness;
+        let scores = self.selection_scores();
+        let best = self.sort_by_scores(&self.finite_fitness())[0].brain.clone();
+        match &mut self.strategy {
+            Some(strategy) => strategy.tell(&samples, &scores),
+            None => {
+                let start = best.params().unwrap();
+                self.strategy = Strategy::new(self.config.optimizer, start);
             }
+        }
+        let Some(strategy) = &self.strategy else {
+            return false;
+        };
+
+        let mut new_games = Vec::with_capacity(self.config.num_games);
+        for params in strategy.ask(self.config.num_games) {
+            let mut new_game = self.new_game();
+            if let Some(brain) = best.with_params(&params) {
+                new_game.brain = brain;
+            }
+            new_games.push(new_game);
+        }
+        self.is_restart_pending = false;
+        self.mutation_boost_gens = 0;
+
+        self.games = new_games;
+        self.batch = Batch::Stale;
+        self.seat_arenas();
+        if SPECIATION_ENABLED {
+            self.species.adjust_threshold();
+            self.species.speciate(&self.games);
+        }
+        true
+    }
+
+    fn new_game(&self) -> Game<B> {
+        Game::with_config(&self.config.game)
+    }
+
+    fn mutate(&self, brain: &mut B) {
+        let boost = if self.mutation_boost_gens > 0 {
+            STAGNATION_MUTATION_BOOST
+        } else {
+            1.0
+        };
 
-            if fitness.is_finite() {
-                weights.push(fitness);
+        brain.mutate(
+            self.config.mutation_rate * boost,
+            self.config.mutation_variation * boost,
+        );
+    }
+
+    /// Per game score used for parent selection, depends on `SELECTION_MODE`
+    /// Shared within species when speciation is enabled
+    fn selection_scores(&mut self) -> Vec<f32> {
+        let scores = self.mode_scores();
+        self.share_scores(scores)
+    }
+
+    /// Per game score of `SELECTION_MODE`, novelty updates the archive so call it once per generation
+    fn mode_scores(&mut self) -> Vec<f32> {
+        let fitness = self.finite_fitness();
+        match SELECTION_MODE {
+            SelectionMode::Fitness => fitness,
+            SelectionMode::Novelty => self.evaluate_novelty(),
+            SelectionMode::Combined => {
+                let novelty = self.evaluate_novelty();
+                let fitness_ranks = rank_normalize(&fitness);
+                let novelty_ranks = rank_normalize(&novelty);
+
+                fitness_ranks
+                    .iter()
+                    .zip(novelty_ranks.iter())
+                    .map(|(f, n)| (1.0 - NOVELTY_WEIGHT) * f + NOVELTY_WEIGHT * n)
+                    .collect()
             }
+            SelectionMode::Pareto => self.pareto_scores(),
         }
-        weights
-            .iter_mut()
-            .for_each(|i| *i = (*i / max_fitness) * 100.0);
+    }
+
+    fn share_scores(&self, scores: Vec<f32>) -> Vec<f32> {
+        if SPECIATION_ENABLED {
+            self.species.shared_scores(&scores)
+        } else {
+            scores
+        }
+    }
+
+    /// Fitness of every game, values that overflowed f32 count as the highest
+    fn finite_fitness(&self) -> Vec<f32> {
+        self.games.iter().map(|g| g.finite_fitness()).collect()
+    }
+
+    /// Position of every game in the crowded comparison order, mapped to 0..1
+    fn pareto_scores(&self) -> Vec<f32> {
+        let points: Vec<Vec<f32>> = self.games.iter().map(pareto::objectives).collect();
+        let order = pareto::crowded_order(&points);
+
+        let mut scores = vec![0.0; self.games.len()];
+        for (pos, &idx) in order.iter().enumerate() {
+            scores[idx] = 1.0 - pos as f32 / self.games.len() as f32;
+        }
+
+        scores
+    }
+
+    fn evaluate_novelty(&mut self) -> Vec<f32> {
+        let behaviours: Vec<Vec<f32>> = self.games.iter().map(|g| g.behaviour()).collect();
+        let novelty = self.archive.evaluate(&behaviours);
+        self.archive.update(&behaviours, &novelty);
 
+        novelty
+    }
+
+    fn sort_by_scores(&self, scores: &[f32]) -> Vec<Game<B>> {
+        let mut order: Vec<usize> = (0..self.games.len()).collect();
+        order.sort_by(|&a, &b| {
+            scores[b]
+                .partial_cmp(&scores[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        order.iter().map(|&i| self.games[i].clone()).collect()
+    }
+
+    fn generate_species_children(&self, scores: &[f32], num_children: usize) -> Vec<Game<B>> {
+        let mut rng = rand::thread_rng();
+        let quotas = self.species.offspring_quotas(scores, num_children);
+        let mut children = Vec::new();
+
+        for (species, quota) in self.species.iter().zip(quotas) {
+            let weights: Vec<f32> = species.members.iter().map(|&idx| scores[idx]).collect();
+            let pool = WeightedIndex::new(&weights).ok();
+
+            for _ in 0..quota {
+                let (parent_1, parent_2) = match &pool {
+                    Some(pool) => (
+                        species.members[pool.sample(&mut rng)],
+                        species.members[pool.sample(&mut rng)],
+                    ),
+                    None => (
+                        species.members[rng.gen_range(0..species.members.len())],
+                        species.members[rng.gen_range(0..species.members.len())],
+                    ),
+                };
+                let mut new_brain = crossover(&self.games[parent_1], &self.games[parent_2]);
+                self.mutate(&mut new_brain);
+
+                let mut child = self.new_game();
+                child.brain = new_brain;
+                children.push(child);
+            }
+        }
+
+        children
+    }
+
+    fn generate_gene_pool(&self, scores: &[f32]) -> Option<WeightedIndex<f32>> {
+        let max_score = scores.iter().cloned().fold(0.0, f32::max);
+        if max_score <= 0.0 {
+            return None;
+        }
+
+        let weights: Vec<f32> = scores.iter().map(|s| (s / max_score) * 100.0).collect();
         WeightedIndex::new(&weights).ok()
     }
 }
+
+/// Child brain of two games, the fitter parent goes first
+fn crossover<B: Brain>(parent_1: &Game<B>, parent_2: &Game<B>) -> B {
+    if parent_2 > parent_1 {
+        parent_2.brain.crossover(&parent_1.brain)
+    } else {
+        parent_1.brain.crossover(&parent_2.brain)
+    }
+}
+
+/// Games left after a partial restart, never fewer than the retained ones
+fn num_kept_after_restart(num_games: usize, num_retained: usize) -> usize {
+    let num_restarted = (num_games as f32 * STAGNATION_RESTART_PERCENT) as usize;
+    num_games.saturating_sub(num_restarted).max(num_retained)
+}
+
+/// Maps values to their rank in 0..1, the lowest value gets 0
+fn rank_normalize(values: &[f32]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| {
+        values[a]
+            .partial_cmp(&values[b])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let max_rank = (values.len().max(2) - 1) as f32;
+    let mut ranks = vec![0.0; values.len()];
+    for (rank, &idx) in order.iter().enumerate() {
+        ranks[idx] = rank as f32 / max_rank;
+    }
+
+    ranks
+}
+
+impl<B: Brain> Default for Stream<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{FitnessFn, VisionMode};
+
+    fn stream() -> Stream {
+        Stream::with_config(StreamConfig {
+            num_games: 20,
+            optimizer: Optimizer::Genetic,
+            arena_size: 1,
+            ..StreamConfig::DEFAULT
+        })
+    }
+
+    #[test]
+    fn stagnation_needs_the_minimum_improvement() {
+        // Fresh games all have a fitness of 1
+        let mut stream = stream();
+        stream.best_fitness = 1.0 / (1.0 + STREAM_STAGNATION_MIN_IMPROVEMENT / 2.0);
+        stream.best_mean_fitness = stream.best_fitness;
+        stream.update_stagnation();
+        assert_eq!(stream.stagnant_gens, 1);
+
+        stream.best_fitness = 1.0 / (1.0 + STREAM_STAGNATION_MIN_IMPROVEMENT * 2.0);
+        stream.update_stagnation();
+        assert_eq!(stream.stagnant_gens, 0);
+        assert_eq!(stream.best_fitness, 1.0);
+    }
+
+    #[test]
+    fn stagnation_counts_up_to_the_limit() {
+        let mut stream = stream();
+        stream.best_fitness = 1.0;
+        stream.best_mean_fitness = 1.0;
+        for _ in 0..STREAM_STAGNATION_GENS {
+            assert!(!stream.is_stagnant());
+            stream.update_stagnation();
+        }
+        assert!(stream.is_stagnant());
+    }
+
+    #[test]
+    fn responses_reset_the_counter_and_boost_for_a_few_gens() {
+        let mut stream = stream();
+        stream.respond_to_stagnation(&[StagnationResponse::MutationBoost]);
+        assert_eq!(stream.mutation_boost_gens, 0);
+
+        stream.stagnant_gens = STREAM_STAGNATION_GENS;
+        stream.respond_to_stagnation(&[
+            StagnationResponse::MutationBoost,
+            StagnationResponse::PartialRestart,
+        ]);
+        assert_eq!(stream.stagnant_gens, 0);
+        assert_eq!(stream.mutation_boost_gens, STAGNATION_MUTATION_BOOST_GENS);
+        assert!(stream.is_restart_pending);
+
+        stream.reset();
+        assert!(!stream.is_restart_pending);
+        assert_eq!(stream.games.len(), 20);
+        for _ in 0..STAGNATION_MUTATION_BOOST_GENS {
+            stream.reset();
+        }
+        assert_eq!(stream.mutation_boost_gens, 0);
+    }
+
+    #[test]
+    fn migrants_keep_the_board_they_were_scored_on() {
+        let source_game = GameConfig {
+            grid_w: 12,
+            grid_h: 12,
+            fitness: FitnessFn::Quadratic,
+            ..GameConfig::DEFAULT
+        };
+        let mut migrant: Game = Game::with_config(&source_game);
+        while !migrant.is_complete {
+            migrant.update();
+        }
+        let fitness = migrant.fitness();
+
+        let mut stream = stream();
+        stream.inject(&[migrant], ReplacementPolicy::Worst);
+        let seated = stream.games.iter().find(|g| g.config.grid_w == 12).unwrap();
+        assert_eq!(seated.config.fitness, FitnessFn::Quadratic);
+        assert_eq!(seated.fitness(), fitness);
+
+        stream.reset();
+        assert!(stream.games.iter().all(|g| g.config.grid_w == GRID_W));
+    }
+
+    #[test]
+    fn partial_restart_keeps_the_retained_games() {
+        let num_restarted = (100.0 * STAGNATION_RESTART_PERCENT) as usize;
+        assert_eq!(num_kept_after_restart(100, 1), 100 - num_restarted);
+        assert_eq!(num_kept_after_restart(100, 99), 99);
+        assert_eq!(num_kept_after_restart(0, 0), 0);
+    }
+
+    #[test]
+    fn seeding_skips_brains_that_dont_parse_or_fit() {
+        let dir = std::env::temp_dir().join("snake_stream_seeding");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let fits = Net::random(&GameConfig::DEFAULT);
+        let mut other_shape = fits.shape();
+        other_shape[0] += 1;
+        let other_board = Net::with_shape(&other_shape);
+        fs::write(dir.join("brain_0.txt"), fits.to_text()).unwrap();
+        fs::write(dir.join("brain_1.txt"), other_board.to_text()).unwrap();
+        fs::write(dir.join("brain_2.txt"), "neat 2 1\nconn 0 3 0.5 1\n").unwrap();
+        fs::write(dir.join("brain_3.txt"), "not a brain").unwrap();
+        fs::write(dir.join("index.csv"), "rank,file\n").unwrap();
+
+        let brains: Vec<Net> = hall_of_fame::load_brains(&dir).unwrap();
+        assert_eq!(brains.len(), 2);
+
+        let config = StreamConfig {
+            num_games: 4,
+            ..stream().config
+        };
+        let seeded: Stream = Stream::from_dir(config.clone(), &dir, SeedFill::Random).unwrap();
+        assert_eq!(seeded.games[0].brain.params(), fits.params());
+        assert!(seeded.games[1..]
+            .iter()
+            .all(|g| g.brain.shape() == fits.shape()));
+
+        let eight_dir = StreamConfig {
+            game: GameConfig {
+                vision: VisionMode::EightDir,
+                ..config.game.clone()
+            },
+            ..config
+        };
+        assert!(Stream::<Net>::from_saved(eight_dir.clone(), &brains, SeedFill::Random).is_none());
+        assert!(Stream::<Net>::from_dir(eight_dir, &dir, SeedFill::Random).is_err());
+    }
+}
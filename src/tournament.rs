@@ -0,0 +1,313 @@
+//! Tournament
+//! Round-robin between brains, solo on the same seeded games or head-to-head in the arena
+//! Every pair of brains plays one match per seed, ranked by win rate
+
+use crate::arena;
+use crate::brain::Brain;
+use crate::eval::{self, EvalResult};
+use crate::game::{Game, GameConfig};
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TournamentMode {
+    /// Every brain plays alone, a match is won by the higher score on the same seed
+    Solo,
+    /// Both brains share the board, a match is won by the top of `arena::ranking`
+    Arena,
+}
+
+#[derive(Clone, Debug)]
+pub struct Standing {
+    pub name: String,
+    /// Scores of every game the brain played
+    pub eval: EvalResult,
+    pub num_matches: usize,
+    /// Draws count as half a win
+    pub wins: f32,
+    pub elo: f32,
+}
+
+impl Standing {
+    pub fn win_rate(&self) -> f32 {
+        self.wins / self.num_matches.max(1) as f32
+    }
+}
+
+/// Standings of the named brains, best first
+pub fn run<B: Brain>(
+    brains: &[(String, B)],
+    config: &GameConfig,
+    mode: TournamentMode,
+    seeds: &[u64],
+) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = brains
+        .iter()
+        .map(|(name, _)| Standing {
+            name: name.clone(),
+            eval: EvalResult::default(),
+            num_matches: 0,
+            wins: 0.0,
+            elo: ELO_INITIAL_RATING,
+        })
+        .collect();
+
+    // Solo games don't depend on the opponent, play them once
+    let mut scores: Vec<Vec<f32>> = match mode {
+        TournamentMode::Solo => brains
+            .iter()
+            .map(|(_, brain)| solo_scores(brain, config, seeds))
+            .collect(),
+        TournamentMode::Arena => vec![Vec::new(); brains.len()],
+    };
+    let mut matches = Vec::new();
+
+    for a in 0..brains.len() {
+        for b in a + 1..brains.len() {
+            for (game_idx, &seed) in seeds.iter().enumerate() {
+                let result = match mode {
+                    TournamentMode::Solo => compare(scores[a][game_idx], scores[b][game_idx]),
+                    TournamentMode::Arena => {
+                        // Swap the spawns every other game
+                        let swap = game_idx % 2 == 1;
+                        let (first, second) = if swap { (b, a) } else { (a, b) };
+                        let pair = [brains[first].1.clone(), brains[second].1.clone()];
+                        let mut snakes = arena::new_arena(config, &pair, seed);
+                        arena::play(&mut snakes);
+                        scores[first].push(snakes[0].score() as f32);
+                        scores[second].push(snakes[1].score() as f32);
+
+                        let result = if is_tie(&snakes[0], &snakes[1]) {
+                            0.5
+                        } else if arena::ranking(&snakes)[0] == 0 {
+                            1.0
+                        } else {
+                            0.0
+                        };
+                        if swap {
+                            1.0 - result
+                        } else {
+                            result
+                        }
+                    }
+                };
+                record_match(&mut standings, a, b, result);
+                matches.push((a, b, result));
+            }
+        }
+    }
+
+    update_elo(&mut standings, &matches);
+    for (standing, scores) in standings.iter_mut().zip(scores.iter()) {
+        standing.eval = EvalResult::from_scores(scores);
+    }
+    standings.sort_by(|x, y| {
+        y.win_rate()
+            .partial_cmp(&x.win_rate())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(
+                y.eval
+                    .mean
+                    .partial_cmp(&x.eval.mean)
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+    });
+    standings
+}
+
+/// Seeds shared by every brain of a tournament
+pub fn tournament_seeds(num_games: usize) -> Vec<u64> {
+    (0..num_games as u64).map(|i| TOURNAMENT_SEED + i).collect()
+}
+
+/// Ranking table for the terminal
+pub fn to_table(standings: &[Standing]) -> String {
+    let name_width = standings
+        .iter()
+        .map(|s| s.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let mut lines = vec![format!(
+        "{:>4}  {:<name_width$}  {:>8}  {:>8}  {:>8}  {:>7}  {:>7}",
+        "Rank", "Name", "Mean", "Std", "Win %", "Elo", "Matches"
+    )];
+    for (rank, s) in standings.iter().enumerate() {
+        lines.push(format!(
+            "{:>4}  {:<name_width$}  {:>8.2}  {:>8.2}  {:>8.1}  {:>7.0}  {:>7}",
+            rank + 1,
+            s.name,
+            s.eval.mean,
+            s.eval.std,
+            s.win_rate() * 100.0,
+            s.elo,
+            s.num_matches
+        ));
+    }
+
+    lines.join("\n")
+}
+
+pub fn to_csv(standings: &[Standing]) -> String {
+    let mut lines = vec!["rank,name,mean_score,std_score,win_rate,elo,matches".to_string()];
+    for (rank, s) in standings.iter().enumerate() {
+        lines.push(format!(
+            "{},{},{:.2},{:.2},{:.3},{:.1},{}",
+            rank + 1,
+            csv_field(&s.name),
+            s.eval.mean,
+            s.eval.std,
+            s.win_rate(),
+            s.elo,
+            s.num_matches
+        ));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn solo_scores<B: Brain>(brain: &B, config: &GameConfig, seeds: &[u64]) -> Vec<f32> {
+    seeds
+        .iter()
+        .map(|&seed| {
+            let mut game = Game::<B>::with_seed(config, seed);
+            game.brain = brain.clone();
+            eval::play(&mut game) as f32
+        })
+        .collect()
+}
+
+/// 1 when `a` wins, 0.5 for a draw
+fn compare(a: f32, b: f32) -> f32 {
+    match a.partial_cmp(&b) {
+        Some(std::cmp::Ordering::Greater) => 1.0,
+        Some(std::cmp::Ordering::Less) => 0.0,
+        _ => 0.5,
+    }
+}
+
+fn is_tie<B: Brain>(a: &Game<B>, b: &Game<B>) -> bool {
+    a.score() == b.score() && a.num_steps() == b.num_steps()
+}
+
+/// Wins and match counts of both players, `result` is the share of the match won by `a`
+fn record_match(standings: &mut [Standing], a: usize, b: usize, result: f32) {
+    standings[a].wins += result;
+    standings[b].wins += 1.0 - result;
+    standings[a].num_matches += 1;
+    standings[b].num_matches += 1;
+}
+
+/// Elo of every player from all the matches, the same whatever order they were played in
+/// Every pass moves the ratings by the mean surprise of their matches, until they settle
+/// Each player also gets a draw against the initial rating, unbeaten players stay finite
+fn update_elo(standings: &mut [Standing], matches: &[(usize, usize, f32)]) {
+    let expected = |a: f32, b: f32| 1.0 / (1.0 + 10.0_f32.powf((b - a) / 400.0));
+    for _ in 0..ELO_MAX_PASSES {
+        let mut surprises: Vec<f32> = standings
+            .iter()
+            .map(|s| 0.5 - expected(s.elo, ELO_INITIAL_RATING))
+            .collect();
+        for &(a, b, result) in matches.iter() {
+            let surprise = result - expected(standings[a].elo, standings[b].elo);
+            surprises[a] += surprise;
+            surprises[b] -= surprise;
+        }
+
+        let mut max_change: f32 = 0.0;
+        for (standing, surprise) in standings.iter_mut().zip(surprises) {
+            let change = ELO_K_FACTOR * surprise / (standing.num_matches + 1) as f32;
+            standing.elo += change;
+            max_change = max_change.max(change.abs());
+        }
+        if max_change < ELO_TOLERANCE {
+            break;
+        }
+    }
+}
+
+/// Quoted when it has a comma, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standings(names: &[&str]) -> Vec<Standing> {
+        names
+            .iter()
+            .map(|name| Standing {
+                name: name.to_string(),
+                eval: EvalResult::default(),
+                num_matches: 0,
+                wins: 0.0,
+                elo: ELO_INITIAL_RATING,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn record_match_splits_the_result() {
+        let mut standings = standings(&["a", "b"]);
+        record_match(&mut standings, 0, 1, 1.0);
+        record_match(&mut standings, 0, 1, 0.5);
+        record_match(&mut standings, 1, 0, 1.0);
+
+        assert_eq!(standings[0].wins, 1.5);
+        assert_eq!(standings[1].wins, 1.5);
+        assert_eq!(standings[0].num_matches, 3);
+        assert_eq!(standings[0].win_rate(), 0.5);
+    }
+
+    #[test]
+    fn elo_does_not_depend_on_the_match_order() {
+        let matches = [
+            (0, 1, 1.0),
+            (1, 2, 0.0),
+            (0, 2, 0.5),
+            (0, 1, 0.0),
+            (2, 1, 1.0),
+        ];
+        let rate = |matches: &[(usize, usize, f32)]| {
+            let mut standings = standings(&["a", "b", "c"]);
+            for &(a, b, result) in matches.iter() {
+                record_match(&mut standings, a, b, result);
+            }
+            update_elo(&mut standings, matches);
+            standings.iter().map(|s| s.elo).collect::<Vec<f32>>()
+        };
+
+        let mut reversed = matches;
+        reversed.reverse();
+        let swapped: Vec<(usize, usize, f32)> =
+            matches.iter().map(|&(a, b, r)| (b, a, 1.0 - r)).collect();
+        let elo = rate(&matches);
+        for other in [rate(&reversed), rate(&swapped)] {
+            assert!(elo.iter().zip(other).all(|(a, b)| (a - b).abs() < 0.01));
+        }
+        // b lost 3 of its 4 matches
+        assert!(elo[1] < elo[0] && elo[1] < elo[2]);
+
+        // Unbeaten players settle too
+        let mut standings = standings(&["a", "b"]);
+        let matches = vec![(0, 1, 1.0); 20];
+        for &(a, b, result) in matches.iter() {
+            record_match(&mut standings, a, b, result);
+        }
+        update_elo(&mut standings, &matches);
+        assert!(standings[0].elo > standings[1].elo);
+        assert!(standings[0].elo < ELO_INITIAL_RATING + 1000.0);
+    }
+
+    #[test]
+    fn csv_quotes_names_with_separators() {
+        assert_eq!(csv_field("hall_of_fame/0.txt"), "hall_of_fame/0.txt");
+        assert_eq!(csv_field("runs/a,b.txt"), "\"runs/a,b.txt\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
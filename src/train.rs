@@ -0,0 +1,312 @@
+//! Training
+//! Supervised pretraining from recorded (vision, action) demonstrations
+//! Demonstrations come from a scripted path-finding agent or recorded human play
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+
+use crate::brain::Brain;
+use crate::food::FoodKind;
+use crate::game::{Game, GameConfig};
+use crate::*;
+
+#[derive(Clone, Debug)]
+pub struct Demo {
+    pub vision: Vec<f64>,
+    pub action: FourDirs,
+}
+
+/// Plays games with the scripted agent and records what it saw and did
+pub fn record_scripted_demos(config: &GameConfig, num_games: usize) -> Vec<Demo> {
+    let mut demos = Vec::new();
+    for _ in 0..num_games {
+        let mut game: Game = Game::with_config(config);
+        while !game.is_complete {
+            let action = scripted_action(&game);
+            demos.push(Demo {
+                vision: game.observe(),
+                action,
+            });
+            game.update_with_dir(action);
+        }
+    }
+
+    demos
+}
+
+/// First step of the shortest path to the closest food, the body is treated as static
+/// Poison is avoided like the walls
+/// Any safe step when there is no path, the current direction when there is none
+pub fn scripted_action<B: Brain>(game: &Game<B>) -> FourDirs {
+    let is_poison = |pt: Point| {
+        game.food
+            .iter()
+            .any(|f| f.pos == pt && f.kind == FoodKind::Poison)
+    };
+    let is_free = |pt: Point| !game.is_wall(pt) && !game.is_snake_body(pt) && !is_poison(pt);
+    let is_edible = |pt: Point| game.food.iter().any(|f| f.pos == pt && f.kind.is_edible());
+    let dirs = [
+        FourDirs::Left,
+        FourDirs::Right,
+        FourDirs::Bottom,
+        FourDirs::Top,
+    ];
+    let step = |pt: Point, dir: FourDirs| {
+        game.wrap(Point::new(pt.x + dir.value().0, pt.y + dir.value().1))
+    };
+    let is_reverse = |dir: FourDirs| {
+        dir != game.dir
+            && ((dir.is_horizontal() && game.dir.is_horizontal())
+                || (dir.is_vertical() && game.dir.is_vertical()))
+    };
+
+    // Breadth first search, remembering the first step of every path
+    let (w, h) = (game.config.grid_w, game.config.grid_h);
+    let cell = |pt: Point| (pt.y * (w + 1) + pt.x) as usize;
+    let mut visited = vec![false; ((w + 1) * (h + 1)) as usize];
+    let mut queue = VecDeque::new();
+    for &dir in dirs.iter().filter(|&&d| !is_reverse(d)) {
+        let next = step(game.head, dir);
+        if is_free(next) {
+            visited[cell(next)] = true;
+            queue.push_back((next, dir));
+        }
+    }
+    while let Some((pt, first_dir)) = queue.pop_front() {
+        if is_edible(pt) {
+            return first_dir;
+        }
+
+        for &dir in dirs.iter() {
+            let next = step(pt, dir);
+            if is_free(next) && !visited[cell(next)] {
+                visited[cell(next)] = true;
+                queue.push_back((next, first_dir));
+            }
+        }
+    }
+
+    dirs.iter()
+        .copied()
+        .find(|&d| !is_reverse(d) && is_free(step(game.head, d)))
+        .unwrap_or(game.dir)
+}
+
+/// Gradient descent over shuffled demos, returns the mean loss of the last epoch
+/// Every demo is seen with a zeroed memory, the feedback weights of recurrent layers aren't trained
+/// None when the brain can't be trained
+pub fn train<B: Brain>(
+    brain: &mut B,
+    demos: &[Demo],
+    epochs: usize,
+    learning_rate: f64,
+) -> Option<f64> {
+    let mut rng = rand::thread_rng();
+    let mut order: Vec<usize> = (0..demos.len()).collect();
+    let mut loss = 0.0;
+
+    for _ in 0..epochs {
+        order.shuffle(&mut rng);
+        let mut total = 0.0;
+        for &idx in order.iter() {
+            let demo = &demos[idx];
+            total += brain.train_step(&demo.vision, &action_targets(demo.action), learning_rate)?;
+        }
+        loss = total / demos.len().max(1) as f64;
+    }
+
+    Some(loss)
+}
+
+/// Share of the demos where the brain picks the same action
+pub fn accuracy<B: Brain>(brain: &B, demos: &[Demo]) -> f32 {
+    let num_correct = demos
+        .iter()
+        .filter(|demo| {
+            let output = brain.predict(&demo.vision, &mut B::State::default());
+            let max_index = output
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(i, _)| i);
+            max_index == Some(action_index(demo.action))
+        })
+        .count();
+
+    num_correct as f32 / demos.len().max(1) as f32
+}
+
+/// Brain fitted to the demos along with how well it fits them
+pub struct Pretrained<B: Brain> {
+    pub brain: B,
+    pub num_demos: usize,
+    pub loss: f64,
+    pub accuracy: f32,
+}
+
+/// Random brain trained on the recorded demos and fresh demos of the scripted agent
+/// Recorded demos that don't match the vision of `config` are left out
+/// None when the brain can't be trained
+pub fn pretrained_brain<B: Brain>(config: &GameConfig, recorded: &[Demo]) -> Option<Pretrained<B>> {
+    let vision_len = Game::<B>::with_config(config).observe().len();
+    let mut demos: Vec<Demo> = recorded
+        .iter()
+        .filter(|d| d.vision.len() == vision_len)
+        .cloned()
+        .collect();
+    demos.extend(record_scripted_demos(config, PRETRAIN_DEMO_GAMES));
+
+    let mut brain = B::random(config);
+    let loss = train(&mut brain, &demos, PRETRAIN_EPOCHS, PRETRAIN_LEARNING_RATE)?;
+    let accuracy = accuracy(&brain, &demos);
+
+    Some(Pretrained {
+        brain,
+        num_demos: demos.len(),
+        loss,
+        accuracy,
+    })
+}
+
+/// Records what the player saw before taking the step
+pub fn record_step<B: Brain>(game: &mut Game<B>, dir: FourDirs, demos: &mut Vec<Demo>) {
+    if game.is_complete {
+        return;
+    }
+
+    demos.push(Demo {
+        vision: game.observe(),
+        action: dir,
+    });
+    game.update_with_dir(dir);
+}
+
+/// One demo per line, the action followed by the vision
+pub fn demos_to_text(demos: &[Demo]) -> String {
+    let mut lines = vec![format!("demos {}", demos.len())];
+    for demo in demos.iter() {
+        let mut values = vec![action_index(demo.action).to_string()];
+        values.extend(demo.vision.iter().map(|v| v.to_string()));
+        lines.push(values.join(" "));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+pub fn demos_from_text(text: &str) -> io::Result<Vec<Demo>> {
+    let bad_data = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| bad_data("Missing demos header"))?
+        .split_whitespace()
+        .collect();
+    let num_demos = match header[..] {
+        ["demos", n] => n.parse::<usize>().map_err(|_| bad_data("Bad demo count"))?,
+        _ => return Err(bad_data("Bad demos header")),
+    };
+
+    let mut demos = Vec::with_capacity(num_demos);
+    for line in lines {
+        let mut values = line.split_whitespace();
+        let action = match values.next().and_then(|v| v.parse::<usize>().ok()) {
+            Some(0) => FourDirs::Left,
+            Some(1) => FourDirs::Right,
+            Some(2) => FourDirs::Bottom,
+            Some(3) => FourDirs::Top,
+            _ => return Err(bad_data("Bad demo action")),
+        };
+        let vision = values
+            .map(|v| v.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| bad_data("Bad demo vision"))?;
+        demos.push(Demo { vision, action });
+    }
+    if demos.len() != num_demos {
+        return Err(bad_data("Wrong number of demos"));
+    }
+
+    Ok(demos)
+}
+
+pub fn save_demos(demos: &[Demo], path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, demos_to_text(demos))
+}
+
+pub fn load_demos(path: &Path) -> io::Result<Vec<Demo>> {
+    demos_from_text(&fs::read_to_string(path)?)
+}
+
+/// Index of the brain output for the direction, same order as `Game` reads them
+fn action_index(dir: FourDirs) -> usize {
+    match dir {
+        FourDirs::Left => 0,
+        FourDirs::Right => 1,
+        FourDirs::Bottom => 2,
+        FourDirs::Top => 3,
+    }
+}
+
+fn action_targets(dir: FourDirs) -> Vec<f64> {
+    let mut targets = vec![0.0; OUTPUT_LAYER_SIZE];
+    targets[action_index(dir)] = 1.0;
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::Net;
+
+    #[test]
+    fn demos_text_round_trip() {
+        let demos = vec![
+            Demo {
+                vision: vec![0.5, -1.0, 0.0],
+                action: FourDirs::Top,
+            },
+            Demo {
+                vision: vec![1.0, 0.25, 2.0],
+                action: FourDirs::Left,
+            },
+        ];
+        let loaded = demos_from_text(&demos_to_text(&demos)).unwrap();
+        assert_eq!(loaded.len(), demos.len());
+        for (a, b) in loaded.iter().zip(demos.iter()) {
+            assert_eq!(a.vision, b.vision);
+            assert_eq!(a.action, b.action);
+        }
+
+        assert!(demos_from_text("demos 2\n0 1.0\n").is_err());
+        assert!(demos_from_text("demos 1\n4 1.0\n").is_err());
+        assert!(demos_from_text("brains 1\n0 1.0\n").is_err());
+    }
+
+    #[test]
+    fn loss_falls_over_the_epochs() {
+        let mut demos = record_scripted_demos(&GameConfig::DEFAULT, 1);
+        demos.truncate(500);
+        let mut brain = Net::random(&GameConfig::DEFAULT);
+        // Without a step the loss is the one of the random brain
+        let first_loss = train(&mut brain, &demos, 1, 0.0).unwrap();
+        let first_accuracy = accuracy(&brain, &demos);
+
+        let mut last_loss = first_loss;
+        for _ in 0..5 {
+            let loss = train(&mut brain, &demos, 4, PRETRAIN_LEARNING_RATE).unwrap();
+            assert!(loss < last_loss);
+            last_loss = loss;
+        }
+
+        assert!(last_loss < first_loss * 0.8);
+        assert!(accuracy(&brain, &demos) > first_accuracy.max(0.6));
+    }
+}
@@ -1,8 +1,6 @@
 use macroquad::color::Color;
 use rand::Rng;
 
-use crate::*;
-
 #[derive(Default, PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct Point {
     pub x: i32,
@@ -37,8 +35,7 @@ pub fn are_colors_equal(c1: Color, c2: Color) -> bool {
 }
 
 impl FourDirs {
-    pub fn get_rand_dir() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn get_rand_dir(rng: &mut impl Rng) -> Self {
         match rng.gen_range(0..4) {
             0 => Self::Left,
             1 => Self::Right,
@@ -82,19 +79,11 @@ impl FourDirs {
     }
 
     pub fn is_horizontal(&self) -> bool {
-        match self {
-            FourDirs::Left => true,
-            FourDirs::Right => true,
-            _ => false,
-        }
+        matches!(self, FourDirs::Left | FourDirs::Right)
     }
 
     pub fn is_vertical(&self) -> bool {
-        match self {
-            FourDirs::Top => true,
-            FourDirs::Bottom => true,
-            _ => false,
-        }
+        matches!(self, FourDirs::Top | FourDirs::Bottom)
     }
 }
 
@@ -103,20 +92,20 @@ impl Point {
         Self { x, y }
     }
 
-    pub fn rand() -> Self {
-        let mut rng = rand::thread_rng();
+    /// Random cell inside the walls of a `grid_w` x `grid_h` board
+    pub fn rand(rng: &mut impl Rng, grid_w: i32, grid_h: i32) -> Self {
         Self {
-            x: rng.gen_range(1..GRID_W - 1),
-            y: rng.gen_range(1..GRID_H - 1),
+            x: rng.gen_range(1..grid_w - 1),
+            y: rng.gen_range(1..grid_h - 1),
         }
     }
 }
 
-impl Into<Point> for (i32, i32) {
-    fn into(self) -> Point {
+impl From<(i32, i32)> for Point {
+    fn from(value: (i32, i32)) -> Self {
         Point {
-            x: self.0,
-            y: self.1,
+            x: value.0,
+            y: value.1,
         }
     }
 }
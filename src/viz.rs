@@ -5,17 +5,21 @@ use macroquad::prelude::*;
 
 use std::time::Instant;
 
-use crate::game::Game;
+use crate::brain::Brain;
+use crate::food::FoodKind;
+use crate::game::{Game, GameConfig};
 use crate::nn::Net;
 use crate::pop::GenerationSummary;
 use crate::*;
 
-pub struct Viz {
-    games: Vec<Game>,
+pub struct Viz<B: Brain = Net> {
+    games: Vec<Game<B>>,
     sim_start_ts: Instant,
     max_score: usize,
+    best_avg_score: f32,
     gen_count: usize,
-    best_brain: Option<Net>,
+    best_brain: Option<B>,
+    best_game_config: GameConfig,
 
     is_slow_mode: bool,
     is_show_viz: bool,
@@ -27,7 +31,10 @@ struct Colors {
     snake_head: Color,
     snake_body: Color,
     food: Color,
+    bonus_food: Color,
+    poison: Color,
     wall: Color,
+    obstacle: Color,
     text: Color,
     node_enabled: Color,
     node_disabled: Color,
@@ -38,14 +45,16 @@ struct Colors {
     opacity: f32,
 }
 
-impl Viz {
+impl<B: Brain> Viz<B> {
     pub fn new() -> Self {
         Self {
             games: Vec::new(),
             sim_start_ts: Instant::now(),
             max_score: 0,
+            best_avg_score: 0.0,
             gen_count: 0,
             best_brain: None,
+            best_game_config: GameConfig::default(),
             is_slow_mode: false,
             is_show_viz: false,
             colors: if VIZ_DARK_THEME {
@@ -76,7 +85,7 @@ impl Viz {
     }
 
     fn init_games(&mut self) {
-        let new_brain = Net::new();
+        let new_brain = B::random(&self.best_game_config);
         let brain = match &self.best_brain {
             Some(brain) => brain,
             None => &new_brain,
@@ -85,20 +94,28 @@ impl Viz {
         let num_games = 100;
         let mut games = Vec::new();
         for _ in 0..num_games {
-            games.push(Game::with_brain(brain));
+            let mut game = Game::with_config(&self.best_game_config);
+            game.brain = brain.clone();
+            games.push(game);
         }
         self.games = games;
     }
 
-    pub fn reset(&mut self, summary: GenerationSummary, gen_count: usize) {
-        if summary.max_score > self.max_score {
-            self.max_score = summary.max_score;
-            self.best_brain = summary.best_net;
+    pub fn reset(&mut self, summary: GenerationSummary<B>, gen_count: usize) {
+        self.max_score = self.max_score.max(summary.max_score);
+        self.best_avg_score = summary.best_avg_score;
+
+        // The hall of fame best, only changes when a better brain shows up
+        if summary.best_net.is_some() {
+            self.best_brain = summary.best_net.clone();
             // self.init_games();
         }
+        if let Some(config) = &summary.best_game_config {
+            self.best_game_config = config.clone();
+        }
 
         self.gen_count = gen_count;
-        self.print_gen_info(summary.max_score);
+        self.print_gen_info(&summary);
     }
 
     pub fn draw(&self) {
@@ -109,11 +126,36 @@ impl Viz {
         self.draw_net();
     }
 
+    /// Only the game played with the arrow keys while recording demos
+    pub fn draw_human_game(&self, game: &Game<B>, num_demos: usize) {
+        clear_background(self.colors.bg);
+        self.draw_game(game, 0, 0, 3.0);
+
+        let w = screen_width() * 0.78;
+        let h = screen_height() * 0.07;
+        draw_text("Recording", w, h, 50.0, self.colors.positive);
+        draw_text(
+            format!("Demos: {:?}", num_demos).as_str(),
+            w,
+            h + 40.0,
+            50.0,
+            self.colors.text,
+        );
+        draw_text("[Arrows] - Steer", w, h + 250.0, 30.0, self.colors.text);
+        draw_text(
+            "[P] - Save demos and resume",
+            w,
+            h + 280.0,
+            30.0,
+            self.colors.text,
+        );
+    }
+
     fn draw_best_games(&self) {
         let mut pos_x = 0;
         let mut pos_y = 0;
 
-        if self.games.len() <= 0 || !self.is_show_viz {
+        if self.games.is_empty() || !self.is_show_viz {
             return;
         }
 
@@ -123,7 +165,7 @@ impl Viz {
         best_games.reverse();
 
         for index in 0..(VIZ_GRID_H * VIZ_GRID_W) {
-            if !grid_zero.contains(&(index as i32)) {
+            if !grid_zero.contains(&index) {
                 let game = &best_games[index as usize];
                 self.draw_game(game, pos_x, pos_y, 1.0);
             }
@@ -143,29 +185,42 @@ impl Viz {
         self.draw_game(game, 0, 0, 1.96);
     }
 
-    fn draw_game(&self, game: &Game, pos_x: i32, pos_y: i32, scale: f32) {
+    fn draw_game(&self, game: &Game<B>, pos_x: i32, pos_y: i32, scale: f32) {
         let padding = 10.0;
         let w = (screen_width() - padding * 2.0) * 0.7;
         let h = (screen_height() - padding * 2.0) * 0.99;
         let sq = w.min(h);
-        let tile_size = ((sq / 4.0) / GRID_W as f32) * scale as f32;
+        // Smaller boards get bigger tiles, every game takes the same space
+        let (grid_w, grid_h) = (game.config.grid_w, game.config.grid_h);
+        let tile_size = ((sq / 4.0) / grid_w as f32) * scale;
 
-        for x in 0..=GRID_W {
-            for y in 0..=GRID_H {
+        for x in 0..=grid_w {
+            for y in 0..=grid_h {
                 let mut color = self.colors.bg;
                 let pt = (x, y).into();
+                let is_border = x == 0 || y == 0 || x == grid_w || y == grid_h;
 
-                if game.is_wall(pt) {
+                if is_border && game.config.wrap {
+                    // Open edges of a toroidal board
+                    color = color_with_a(self.colors.wall, self.colors.opacity);
+                } else if game.is_wall(pt) {
                     color = self.colors.wall;
                 }
-                if game.is_snake_body(pt) {
+                if game.is_obstacle(pt) {
+                    color = self.colors.obstacle;
+                }
+                if !is_border && game.is_snake_body(pt) {
                     color = self.colors.snake_body;
                 }
                 if game.head == pt {
                     color = self.colors.snake_head;
                 }
-                if game.food == pt {
-                    color = self.colors.food;
+                if let Some(food) = game.food.iter().find(|f| f.pos == pt) {
+                    color = match food.kind {
+                        FoodKind::Normal => self.colors.food,
+                        FoodKind::Bonus => self.colors.bonus_food,
+                        FoodKind::Poison => self.colors.poison,
+                    };
                 }
 
                 if game.is_complete {
@@ -173,14 +228,14 @@ impl Viz {
                 }
 
                 let (tx, ty) =
-                    grid_to_world((pos_x * GRID_W) + x, (pos_y * GRID_H) + y, tile_size, 1.0);
+                    grid_to_world((pos_x * grid_w) + x, (pos_y * grid_h) + y, tile_size, 1.0);
                 draw_rectangle(tx + padding, ty + padding, tile_size, tile_size, color);
             }
         }
 
         let (tx, ty) = grid_to_world(
-            (pos_x * GRID_W) + 3,
-            (pos_y * GRID_H) + GRID_H,
+            (pos_x * grid_w) + 3,
+            (pos_y * grid_h) + grid_h,
             tile_size,
             1.0,
         );
@@ -207,114 +262,111 @@ impl Viz {
         let h = screen_height() * 1.00;
 
         let node_border_color = color_with_a(GRAY, 0.0);
-        let node_radius = 25.0;
+        let node_gap = 15.0;
         let node_border_thickness = 2.0;
         let line_thickness = 3.0;
         let y_padding = 120.0;
-        let layer_1_x_padding = 0.0;
-        let layer_2_x_padding = 150.0;
-        let layer_3_x_padding = 300.0;
-
-        let layer_1_y = self.calculate_circle_positions(INP_LAYER_SIZE, node_radius, h, 15.0);
-        let layer_2_y = self.calculate_circle_positions(HIDDEN_LAYER_SIZE, node_radius, h, 15.0);
-        let layer_3_y = self.calculate_circle_positions(OUTPUT_LAYER_SIZE, node_radius, h, 15.0);
-        let (colors1, colors2, colors3) = self.get_node_colors();
+        let layers_x_span = 300.0;
+
+        let layer_colors = self.get_node_colors();
+        let num_layers = layer_colors.len();
+        let mut is_recurrent = vec![false];
+        is_recurrent.extend(self.games[0].brain.recurrent_layers());
+        is_recurrent.resize(num_layers, false);
+        let layer_x: Vec<f32> = (0..num_layers)
+            .map(|i| w + layers_x_span * i as f32 / (num_layers - 1) as f32)
+            .collect();
+
+        // Shrink the nodes when the biggest layer doesn't fit
+        let max_nodes = layer_colors.iter().map(|c| c.len()).max().unwrap_or(1) as f32;
+        let node_radius =
+            ((h * 0.7 - (max_nodes - 1.0) * node_gap) / (2.0 * max_nodes)).clamp(5.0, 25.0);
+        let layer_y: Vec<Vec<f32>> = layer_colors
+            .iter()
+            .map(|c| {
+                self.calculate_circle_positions(c.len(), node_radius, h, node_gap)
+                    .iter()
+                    .map(|y| y + y_padding)
+                    .collect()
+            })
+            .collect();
 
         // Bottom Text
-        let bt_x = screen_width() * 0.75;
-        draw_text(
-            "Input",
-            bt_x + 5.0,
-            screen_height() * 0.97,
-            30.0,
-            self.colors.text,
-        );
-        draw_text(
-            "Hidden",
-            bt_x + layer_2_x_padding,
-            screen_height() * 0.85,
-            30.0,
-            self.colors.text,
-        );
-        draw_text(
-            "Output",
-            bt_x + layer_3_x_padding,
-            screen_height() * 0.725,
-            30.0,
-            self.colors.text,
-        );
+        for (idx, (x, ys)) in layer_x.iter().zip(layer_y.iter()).enumerate() {
+            let text = if idx == 0 {
+                "Input"
+            } else if idx == num_layers - 1 {
+                "Output"
+            } else if is_recurrent[idx] {
+                "Recurrent"
+            } else {
+                "Hidden"
+            };
+            let bottom = ys.last().cloned().unwrap_or(h * 0.5);
+            draw_text(
+                text,
+                x - 30.0,
+                bottom + node_radius * 2.0 + 5.0,
+                30.0,
+                self.colors.text,
+            );
+        }
 
         // Lines
-        for (y1, c1) in layer_1_y.iter().zip(colors1.iter()) {
-            for y2 in layer_2_y.iter() {
-                let color = self.get_line_color(*c1);
-                draw_line(
-                    w + layer_1_x_padding,
-                    *y1 + y_padding,
-                    w + layer_2_x_padding,
-                    *y2 + y_padding,
-                    line_thickness,
-                    color,
-                );
-            }
-        }
-        for y2 in layer_2_y.iter() {
-            for (y3, c3) in layer_3_y.iter().zip(colors3.iter()) {
-                let color = self.get_line_color(*c3);
-                draw_line(
-                    w + layer_2_x_padding,
-                    *y2 + y_padding,
-                    w + layer_3_x_padding,
-                    *y3 + y_padding,
-                    line_thickness,
-                    color,
-                );
+        for idx in 0..num_layers - 1 {
+            for (y1, c1) in layer_y[idx].iter().zip(layer_colors[idx].iter()) {
+                for (y2, c2) in layer_y[idx + 1].iter().zip(layer_colors[idx + 1].iter()) {
+                    let color = self.get_line_color(if idx == 0 { *c1 } else { *c2 });
+                    draw_line(
+                        layer_x[idx],
+                        *y1,
+                        layer_x[idx + 1],
+                        *y2,
+                        line_thickness,
+                        color,
+                    );
+                }
             }
         }
 
         // Nodes
-        for (y, c) in layer_1_y.iter().zip(colors1.iter()) {
-            draw_circle(w + layer_1_x_padding, *y + y_padding, node_radius, *c);
-            draw_circle_lines(
-                w + layer_1_x_padding,
-                *y + y_padding,
-                node_radius,
-                node_border_thickness,
-                node_border_color,
-            );
-        }
-        for (y, c) in layer_2_y.iter().zip(colors2.iter()) {
-            draw_circle(w + layer_2_x_padding, *y + y_padding, node_radius, *c);
-            draw_circle_lines(
-                w + layer_2_x_padding,
-                *y + y_padding,
-                node_radius,
-                node_border_thickness,
-                node_border_color,
-            );
-        }
-        for ((idx, y), c) in layer_3_y.iter().enumerate().zip(colors3.iter()) {
-            let (px, py) = (w + layer_3_x_padding, *y + y_padding);
-            draw_circle(px, py, node_radius, *c);
-            draw_circle_lines(
-                px,
-                py,
-                node_radius,
-                node_border_thickness,
-                node_border_color,
-            );
-            let text = match idx {
-                0 => "Left",
-                1 => "Right",
-                2 => "Bottom",
-                _ => "Top",
-            };
-            let color = if are_colors_equal(*c, self.colors.node_enabled) {
-                self.colors.text
-            } else {
-                self.colors.disabled
-            };
-            draw_text(text, px + 50.0, py + 5.0, 30.0, color);
+        for (idx, (x, ys)) in layer_x.iter().zip(layer_y.iter()).enumerate() {
+            for (node_idx, (y, c)) in ys.iter().zip(layer_colors[idx].iter()).enumerate() {
+                draw_circle(*x, *y, node_radius, *c);
+                draw_circle_lines(
+                    *x,
+                    *y,
+                    node_radius,
+                    node_border_thickness,
+                    node_border_color,
+                );
+                // Ring for the hidden state fed back into the node
+                if is_recurrent[idx] {
+                    draw_circle_lines(
+                        *x,
+                        *y,
+                        node_radius + 4.0,
+                        node_border_thickness,
+                        self.colors.node_hidden,
+                    );
+                }
+
+                if idx != num_layers - 1 {
+                    continue;
+                }
+                let text = match node_idx {
+                    0 => "Left",
+                    1 => "Right",
+                    2 => "Bottom",
+                    _ => "Top",
+                };
+                let color = if are_colors_equal(*c, self.colors.node_enabled) {
+                    self.colors.text
+                } else {
+                    self.colors.disabled
+                };
+                draw_text(text, x + node_radius * 2.0, y + 5.0, 30.0, color);
+            }
         }
     }
 
@@ -359,6 +411,10 @@ impl Viz {
             },
         );
 
+        if self.best_game_config.wrap {
+            draw_text("Board: Wrap", w, h + 160.0, 50.0, self.colors.text);
+        }
+
         if !self.is_show_viz {
             draw_text(
                 "[Space] - Slow motion",
@@ -368,6 +424,20 @@ impl Viz {
                 self.colors.text,
             );
             draw_text("[Tab] - Show Viz", w, h + 280.0, 30.0, self.colors.text);
+            draw_text(
+                "[S] - Save hall of fame",
+                w,
+                h + 310.0,
+                30.0,
+                self.colors.text,
+            );
+            draw_text(
+                "[P] - Play and record demos",
+                w,
+                h + 340.0,
+                30.0,
+                self.colors.text,
+            );
         }
     }
 
@@ -389,7 +459,7 @@ impl Viz {
         self.is_slow_mode = is_slow_mode;
     }
 
-    fn get_node_colors(&self) -> (Vec<Color>, Vec<Color>, Vec<Color>) {
+    fn get_node_colors(&self) -> Vec<Vec<Color>> {
         let mut color_enabled = self.colors.node_enabled;
         let mut color_disabled = self.colors.node_disabled;
         let mut color_hidden = self.colors.node_hidden;
@@ -406,10 +476,9 @@ impl Viz {
             color_hidden = self.colors.disabled;
         }
 
-        let net_out = game.get_net_output();
-        let inputs = net_out[0].clone();
-        let hidden = net_out[1].clone();
-        let output = net_out[2].clone();
+        let mut net_out = game.get_net_output();
+        let output = net_out.pop().unwrap();
+        let inputs = net_out.remove(0);
 
         let mut input_colors = Vec::new();
         for i in inputs.iter() {
@@ -422,16 +491,20 @@ impl Viz {
             }
         }
 
-        let mut hidden_colors = Vec::new();
-        for i in hidden.iter() {
-            let opacity = map_to_unit_interval(*i as f32, 0.5);
-            if game.is_complete {
-                hidden_colors.push(color_with_a(color_hidden, 1.0));
-            } else if opacity.is_finite() {
-                hidden_colors.push(color_with_a(color_hidden, opacity));
-            } else {
-                hidden_colors.push(color_with_a(color_hidden, 0.8));
+        let mut layer_colors = vec![input_colors];
+        for hidden in net_out.iter() {
+            let mut hidden_colors = Vec::new();
+            for i in hidden.iter() {
+                let opacity = map_to_unit_interval(*i as f32, 0.5);
+                if game.is_complete {
+                    hidden_colors.push(color_with_a(color_hidden, 1.0));
+                } else if opacity.is_finite() {
+                    hidden_colors.push(color_with_a(color_hidden, opacity));
+                } else {
+                    hidden_colors.push(color_with_a(color_hidden, 0.8));
+                }
             }
+            layer_colors.push(hidden_colors);
         }
 
         let max_index = output
@@ -447,15 +520,11 @@ impl Viz {
             _ => FourDirs::Top,
         };
 
-        if game.dir.is_horizontal() {
-            if dir.is_horizontal() && game.dir != dir {
-                dir = game.dir;
-            }
+        if game.dir.is_horizontal() && dir.is_horizontal() && game.dir != dir {
+            dir = game.dir;
         }
-        if game.dir.is_vertical() {
-            if dir.is_vertical() && game.dir != dir {
-                dir = game.dir;
-            }
+        if game.dir.is_vertical() && dir.is_vertical() && game.dir != dir {
+            dir = game.dir;
         }
 
         let mut output_colors = vec![
@@ -477,8 +546,8 @@ impl Viz {
             output_colors[3] = color_enabled;
         }
 
-        // colors
-        (input_colors, hidden_colors, output_colors)
+        layer_colors.push(output_colors);
+        layer_colors
     }
 
     fn calculate_circle_positions(&self, n: usize, r: f32, h: f32, y: f32) -> Vec<f32> {
@@ -494,15 +563,90 @@ impl Viz {
         positions
     }
 
-    fn print_gen_info(&self, gen_max_score: usize) {
+    fn print_gen_info(&self, summary: &GenerationSummary<B>) {
         let message = format!(
-            "Gen: {}, Max Score: {}, Gen Max: {}, Sim Ts: {:.2?}m",
+            "Gen: {}, Max Score: {}, Gen Max: {}, Best Avg: {:.2}, Sim Ts: {:.2?}m",
             self.gen_count,
             self.max_score,
-            gen_max_score,
+            summary.max_score,
+            self.best_avg_score,
             self.sim_start_ts.elapsed().as_secs_f32() / 60.0,
         );
         println!("{}", message);
+        if let Some(stage) = summary.curriculum_stage {
+            println!("  Curriculum stage: {}", stage);
+        }
+        println!(
+            "  Games: {}, Wall: {}, Body: {}, Starved: {}, Poisoned: {}",
+            summary.counts.total(),
+            summary.counts.wall,
+            summary.counts.body,
+            summary.counts.starvation,
+            summary.counts.poison
+        );
+
+        if let Some(validation) = &summary.validation {
+            println!(
+                "  Validation: {:.2} ± {:.2} over {} seeds (train score: {}, stream: {})",
+                validation.eval.mean,
+                validation.eval.std,
+                VALIDATION_NUM_GAMES,
+                validation.train_score,
+                validation.stream
+            );
+        }
+
+        for (stream_idx, species) in summary.species.iter().enumerate() {
+            if species.is_empty() {
+                continue;
+            }
+
+            let top_species: Vec<String> = species
+                .iter()
+                .take(5)
+                .map(|s| format!("#{}(n: {}, max: {})", s.id, s.size, s.max_score))
+                .collect();
+            println!(
+                "  Stream {}: {} species, {}",
+                stream_idx,
+                species.len(),
+                top_species.join(" ")
+            );
+        }
+
+        if !summary.pareto_front.is_empty() {
+            let names: Vec<&str> = PARETO_OBJECTIVES.iter().map(|o| o.name()).collect();
+            let points: Vec<String> = summary
+                .pareto_front
+                .iter()
+                .take(8)
+                .map(|p| {
+                    let values: Vec<String> = p
+                        .iter()
+                        .map(|v| {
+                            if *v == f32::MAX {
+                                "-".to_string()
+                            } else {
+                                format!("{:.1}", v)
+                            }
+                        })
+                        .collect();
+                    format!("({})", values.join(", "))
+                })
+                .collect();
+            println!(
+                "  Pareto front ({}): {} points, {}",
+                names.join(", "),
+                summary.pareto_front.len(),
+                points.join(" ")
+            );
+        }
+    }
+}
+
+impl<B: Brain> Default for Viz<B> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -513,7 +657,10 @@ impl Colors {
             snake_head: Color::from_hex(0xe982f4),
             snake_body: Color::from_hex(0x67dbf8),
             food: Color::from_hex(0x7aed86),
+            bonus_food: Color::from_hex(0xf9e55b),
+            poison: Color::from_hex(0xb46cf0),
             wall: Color::from_hex(0xadb4bf),
+            obstacle: Color::from_hex(0xf4b860),
             text: WHITE,
             node_enabled: Color::from_hex(0x7aed86),
             node_disabled: Color::from_hex(0xfb7171),
@@ -531,7 +678,10 @@ impl Colors {
             snake_head: BLUE,
             snake_body: GREEN,
             food: RED,
+            bonus_food: GOLD,
+            poison: PURPLE,
             wall: BROWN,
+            obstacle: DARKBROWN,
             text: BLACK,
             node_enabled: GREEN,
             node_disabled: RED,